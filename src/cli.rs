@@ -0,0 +1,765 @@
+use crate::format::{Template, DEFAULT_FORMAT};
+use clap::{
+    builder::PossibleValuesParser, crate_description, crate_name, crate_version, Arg, ArgAction,
+    ArgMatches, Command,
+};
+use git_up::{Config, OnConflict};
+use std::{path::PathBuf, time::Duration};
+
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+pub enum LogFormat {
+    Text,
+    Ndjson,
+}
+
+pub struct Cli {
+    pub completions: Option<String>,
+    pub undo: Option<PathBuf>,
+    pub verbose: u8,
+    pub color: ColorChoice,
+    pub log_format: LogFormat,
+    pub quiet: bool,
+    pub json: bool,
+    pub porcelain: bool,
+    pub show_stat: bool,
+    // branches are printed once all are done, grouped by what happened instead of as they go
+    pub group: bool,
+    pub timings: bool,
+    pub warnings_as_errors: bool,
+    pub fail_on_protected: bool,
+    pub abbrev: usize,
+    pub format: Template,
+    pub write_undo: Option<PathBuf>,
+    // every -C given; with more than one, each repository is synced in turn
+    pub directories: Vec<PathBuf>,
+    pub repos_file: Option<PathBuf>,
+    pub config: Config,
+}
+
+impl Cli {
+    pub fn parse() -> Self {
+        Self::from_matches(&command().get_matches())
+    }
+
+    fn from_matches(root: &ArgMatches) -> Self {
+        // without a subcommand, git-up syncs using the flags given at the top level
+        let (matches, dry_run, delete_only) = match root.subcommand() {
+            Some(("status", matches)) => (matches, true, false),
+            Some(("clean", matches)) => (matches, matches.get_flag("dry-run"), true),
+            Some(("sync", matches)) => (matches, matches.get_flag("dry-run"), false),
+            _ => (root, root.get_flag("dry-run"), false),
+        };
+        Self {
+            completions: root
+                .subcommand_matches("completions")
+                .and_then(|matches| matches.get_one::<String>("shell").cloned()),
+            undo: root
+                .subcommand_matches("undo")
+                .and_then(|matches| matches.get_one::<PathBuf>("file").cloned()),
+            write_undo: matches.get_one::<PathBuf>("write-undo").cloned(),
+            directories: matches
+                .get_many::<PathBuf>("directory")
+                .map(|directories| directories.cloned().collect())
+                .unwrap_or_default(),
+            repos_file: matches.get_one::<PathBuf>("repos-file").cloned(),
+            verbose: matches.get_count("verbose"),
+            color: match matches.get_one::<String>("color").map(String::as_str) {
+                Some("always") => ColorChoice::Always,
+                Some("never") => ColorChoice::Never,
+                _ => ColorChoice::Auto,
+            },
+            log_format: match matches.get_one::<String>("log-format").map(String::as_str) {
+                Some("ndjson") => LogFormat::Ndjson,
+                _ => LogFormat::Text,
+            },
+            quiet: matches.get_flag("quiet"),
+            json: matches.get_flag("json"),
+            porcelain: matches.contains_id("porcelain"),
+            show_stat: matches.get_flag("show-stat"),
+            group: matches.get_flag("group"),
+            timings: matches.get_flag("timings"),
+            warnings_as_errors: matches.get_flag("warnings-as-errors"),
+            fail_on_protected: matches.get_flag("fail-on-protected"),
+            abbrev: matches.get_one::<u8>("abbrev").copied().unwrap_or(7).into(),
+            format: matches
+                .get_one::<Template>("format")
+                .cloned()
+                .unwrap_or_else(|| Template::parse(DEFAULT_FORMAT).unwrap()),
+            config: Config {
+                directory: root
+                    .subcommand_matches("undo")
+                    .unwrap_or(matches)
+                    .get_one::<PathBuf>("directory")
+                    .cloned(),
+                remote: matches.get_one::<String>("remote").cloned(),
+                default_branch: matches.get_one::<String>("default-branch").cloned(),
+                dry_run,
+                delete_only,
+                no_fetch: matches.get_flag("no-fetch"),
+                all_remotes: matches.get_flag("all-remotes"),
+                prune_tags: matches.get_flag("prune-tags"),
+                rebase: matches.get_flag("rebase"),
+                pull: matches.get_flag("pull"),
+                autostash: matches.get_flag("autostash"),
+                detect_squash: matches.get_flag("detect-squash"),
+                rebase_gone: matches.get_flag("rebase-gone"),
+                prune_merged: matches.get_flag("prune-merged"),
+                local_only: matches.get_flag("local-only"),
+                archive: matches.get_flag("archive"),
+                backup_remote: matches.get_one::<String>("backup-remote").cloned(),
+                force: matches.get_flag("force"),
+                allow_over_cap: matches.get_flag("allow-over-cap"),
+                keep_current: matches.get_flag("keep-current"),
+                on_conflict: match matches.get_one::<String>("on-conflict").map(String::as_str) {
+                    Some("rebase") => OnConflict::Rebase,
+                    Some("merge") => OnConflict::Merge,
+                    Some("abort") => OnConflict::Abort,
+                    _ => OnConflict::Warn,
+                },
+                confirm: matches.get_flag("confirm"),
+                confirm_deletes: matches.get_flag("confirm-deletes"),
+                update_submodules: matches.get_flag("update-submodules"),
+                set_head: matches.get_flag("set-head"),
+                digest: matches.get_flag("digest"),
+                fail_fast: matches.get_flag("fail-fast"),
+                plan: matches.get_flag("plan"),
+                only_current: matches.get_flag("only-current"),
+                interactive: matches.get_flag("interactive"),
+                resume: matches.get_flag("resume"),
+                fetch_timeout: matches
+                    .get_one::<u64>("fetch-timeout")
+                    .map(|secs| Duration::from_secs(*secs)),
+                since: matches.get_one::<Duration>("since").copied(),
+                stale: matches.get_one::<Duration>("stale").copied(),
+                fetch_retries: matches
+                    .get_one::<usize>("fetch-retries")
+                    .copied()
+                    .unwrap_or(2),
+                fetch_args: matches
+                    .get_many::<String>("fetch-arg")
+                    .map(|args| args.cloned().collect())
+                    .unwrap_or_default(),
+                jobs: matches.get_one::<usize>("jobs").copied().unwrap_or(1),
+                max_deletes: matches.get_one::<usize>("max-deletes").copied(),
+                max_updates: matches.get_one::<usize>("max-updates").copied(),
+                branches: matches
+                    .get_many::<String>("branches")
+                    .map(|branches| branches.cloned().collect())
+                    .unwrap_or_default(),
+                exclude: matches
+                    .get_many::<String>("exclude")
+                    .map(|patterns| patterns.cloned().collect())
+                    .unwrap_or_default(),
+                remote_filter: matches.get_one::<String>("remote-filter").cloned(),
+            },
+        }
+    }
+}
+
+pub fn command() -> Command {
+    let command = Command::new(crate_name!())
+        .version(crate_version!())
+        .about(crate_description!())
+        .after_help(
+            "Exit status:\n  \
+             0   nothing needed to change\n  \
+             1   an error occurred, or a branch failed to sync\n  \
+             10  branches were updated or deleted\n  \
+             130 interrupted with Ctrl-C\n\n\
+             After Ctrl-C, a run started with --resume picks up where it stopped when run \
+             with --resume again.",
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print a completion script for bash, zsh, or fish")
+                .hide(true)
+                .arg(
+                    Arg::new("shell")
+                        .required(true)
+                        .value_parser(PossibleValuesParser::new(["bash", "zsh", "fish"])),
+                ),
+        )
+        .subcommand(sync_args(
+            Command::new("sync").about("Update and delete branches (what git-up does by default)"),
+        ))
+        .subcommand(sync_args(
+            Command::new("status").about("Report what sync would do without changing anything"),
+        ))
+        .subcommand(sync_args(Command::new("clean").about(
+            "Only delete merged branches whose upstream is gone, without updating any",
+        )))
+        .subcommand(
+            Command::new("undo")
+                .about("Recreate the branches recorded by --write-undo")
+                .arg(directory_arg())
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ),
+        )
+        // flags belong either before a subcommand's name or after it, not both
+        .args_conflicts_with_subcommands(true);
+    sync_args(command)
+}
+
+fn directory_arg() -> Arg {
+    Arg::new("directory")
+        .short('C')
+        .value_name("PATH")
+        .help(
+            "Run as if git-up was started in PATH instead of the current directory (may be \
+             repeated to sync several repositories)",
+        )
+        .value_parser(clap::value_parser!(PathBuf))
+        .action(ArgAction::Append)
+}
+
+// every flag that affects syncing, shared by running git-up bare and its subcommands
+fn sync_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("branches")
+                .value_name("BRANCH")
+                .help("Only sync these local branches (defaults to every local branch)")
+                .num_args(0..),
+        )
+        .arg(
+            Arg::new("only-current")
+                .long("only-current")
+                .help("Only sync the branch that is checked out")
+                .conflicts_with_all(["branches", "local-only"])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(directory_arg())
+        .arg(
+            Arg::new("repos-file")
+                .long("repos-file")
+                .value_name("FILE")
+                .help("Sync each repository listed in FILE, one path per line")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("remote")
+                .long("remote")
+                .value_name("NAME")
+                .help("Remote to sync against (defaults to the main remote)"),
+        )
+        .arg(
+            Arg::new("default-branch")
+                .long("default-branch")
+                .value_name("BRANCH")
+                .help(
+                    "Branch that merged branches are compared against (defaults to <remote>/HEAD)",
+                ),
+        )
+        .arg(
+            Arg::new("exclude")
+                .long("exclude")
+                .value_name("PATTERN")
+                .help("Skip branches matching this glob pattern (may be repeated)")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("remote-filter")
+                .long("remote-filter")
+                .value_name("REMOTE")
+                .help("Only sync branches tracking REMOTE")
+                .long_help(
+                    "Only sync branches tracking REMOTE. Branches without tracking config are \
+                     included when REMOTE is the one git-up syncs against.",
+                )
+                .conflicts_with("local-only"),
+        )
+        .arg(
+            Arg::new("since")
+                .long("since")
+                .value_name("DURATION")
+                .help("Skip branches with no commits in this long, e.g. 12h, 30d, or 2w")
+                .value_parser(parse_duration),
+        )
+        .arg(
+            Arg::new("stale")
+                .long("stale")
+                .value_name("DAYS")
+                .help("Afterwards, list branches with an upstream but no commits in over DAYS days")
+                .long_help(
+                    "Afterwards, list branches with an upstream but no commits in over DAYS days, \
+                     with when each was last committed to. Nothing is deleted; this is only a \
+                     report, to help find abandoned branches.",
+                )
+                .value_parser(parse_days)
+                .conflicts_with("local-only"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .help("Report what would be updated or deleted without changing anything")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("plan")
+                .long("plan")
+                .help("Show everything that will change and ask before doing any of it")
+                .conflicts_with("json")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("interactive")
+                .long("interactive")
+                .help("Choose which branches to update or delete from a list")
+                .long_help(
+                    "Choose which branches to update or delete from a list, with every change \
+                     selected to start with. Ignored when not attached to a terminal.",
+                )
+                .conflicts_with_all(["json", "plan"])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("resume")
+                .long("resume")
+                .help("Record progress, and skip branches an interrupted run already handled")
+                .long_help(
+                    "Keep track of progress and skip branches already handled by an interrupted \
+                     run. Progress is kept in .git/git-up-state.json until every branch has \
+                     synced without failing. Setting git-up.resume to true does the same for \
+                     every run.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-fetch")
+                .long("no-fetch")
+                .help(
+                    "Skip fetching and use the remote-tracking branches as they are \
+                     (remote state is not refreshed)",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("fetch-timeout")
+                .long("fetch-timeout")
+                .value_name("SECS")
+                .help("Give up on a fetch that takes longer than this")
+                .value_parser(clap::value_parser!(u64).range(1..)),
+        )
+        .arg(
+            Arg::new("fetch-retries")
+                .long("fetch-retries")
+                .value_name("N")
+                .help("Retry a failed or timed out fetch up to N times, backing off between attempts")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("2"),
+        )
+        .arg(
+            Arg::new("fetch-arg")
+                .long("fetch-arg")
+                .value_name("ARG")
+                .help("Pass an extra argument to `git fetch`, e.g. --fetch-arg=--no-progress (may be repeated)")
+                .allow_hyphen_values(true)
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("all-remotes")
+                .long("all-remotes")
+                .help("Fetch every remote and sync branches against their own remote")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("prune-tags")
+                .long("prune-tags")
+                .help("Delete local tags that have been deleted on the remote")
+                .long_help(
+                    "Delete local tags that have been deleted on the remote. Only tags that \
+                     git-up has previously seen on the remote are deleted, so tags created \
+                     locally and never pushed are kept.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("rebase")
+                .long("rebase")
+                .help("Rebase the current branch onto its upstream when they have diverged")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("pull")
+                .long("pull")
+                .help("Run `git pull` on the current branch when it has diverged from its upstream")
+                .long_help(
+                    "Run `git pull` on the current branch when it has diverged from its \
+                     upstream, instead of warning about unpushed commits. Pull honors \
+                     pull.rebase and branch.<name>.rebase, so this can create merge commits \
+                     when those aren't set. Other branches are still only fast-forwarded.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("autostash")
+                .long("autostash")
+                .help("Stash local changes before leaving a branch that is being deleted")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("detect-squash")
+                .long("detect-squash")
+                .help("Treat branches whose changes were squash-merged upstream as merged")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("rebase-gone")
+                .long("rebase-gone")
+                .help("Rebase unmerged branches whose upstream is gone onto the default branch")
+                .long_help(
+                    "Rebase unmerged branches whose upstream is gone onto the default branch. \
+                     Only branches that aren't checked out and have no merge commits since \
+                     their merge base are rebased. If the rebase hits a conflict it is \
+                     aborted and the branch is left untouched.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("prune-merged")
+                .long("prune-merged")
+                .help("Also delete branches merged into the default branch whose upstream still exists")
+                .long_help(
+                    "Also delete branches merged into the default branch whose upstream still \
+                     exists. The current branch and protected branches are never deleted this \
+                     way.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("local-only")
+                .long("local-only")
+                .help("Without fetching, delete branches merged into the local default branch")
+                .long_help(
+                    "Without using any remote, delete branches merged into the local default \
+                     branch. Nothing is fetched, and branches are never fast-forwarded.",
+                )
+                .conflicts_with_all(["remote", "all-remotes", "no-fetch", "set-head", "prune-tags"])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("archive")
+                .long("archive")
+                .help("Keep a ref under refs/git-up/archive/ for each deleted branch")
+                .long_help(
+                    "Keep a ref under refs/git-up/archive/ for each deleted branch, so its \
+                     commits stay reachable. Restore one with \
+                     `git branch <name> refs/git-up/archive/<name>`.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("backup-remote")
+                .long("backup-remote")
+                .value_name("REMOTE")
+                .help("Push each branch to REMOTE before deleting it")
+                .long_help(
+                    "Push each branch to REMOTE before deleting it, so a copy is kept there. A \
+                     branch that fails to push is kept and reported.",
+                ),
+        )
+        .arg(
+            Arg::new("write-undo")
+                .long("write-undo")
+                .value_name("FILE")
+                .help("Record each deleted branch and its tip in FILE, for `git-up undo FILE`")
+                .value_parser(clap::value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("on-conflict")
+                .long("on-conflict")
+                .value_name("POLICY")
+                .help("What to do when the checked-out branch has diverged from its upstream")
+                .long_help(
+                    "What to do when the checked-out branch has diverged from its upstream: \
+                     warn about it, rebase it onto the upstream, merge the upstream into it, \
+                     or abort the run before any branch is changed (the fetch still happens). \
+                     Other branches are only warned about.",
+                )
+                .value_parser(PossibleValuesParser::new(["warn", "rebase", "merge", "abort"]))
+                .default_value("warn"),
+        )
+        .arg(
+            Arg::new("keep-current")
+                .long("keep-current")
+                .help("Keep the checked-out branch when it's merged, instead of deleting it")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("max-deletes")
+                .long("max-deletes")
+                .value_name("N")
+                .help(
+                    "Stop before deleting more than N branches unless confirmed or \
+                     --allow-over-cap",
+                )
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("max-updates")
+                .long("max-updates")
+                .value_name("N")
+                .help(
+                    "Stop before updating more than N branches unless confirmed or \
+                     --allow-over-cap",
+                )
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("force")
+                .long("force")
+                .help("Delete branches without checking again that they're merged")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("allow-over-cap")
+                .long("allow-over-cap")
+                .help("Go past --max-deletes and --max-updates without asking")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("confirm")
+                .short('i')
+                .long("confirm")
+                .help("Ask before deleting each branch (ignored when stdout isn't a terminal)")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("confirm-deletes")
+                .long("confirm-deletes")
+                .help("Ask before deleting each branch, and keep them all without a terminal")
+                .long_help(
+                    "Ask before deleting each branch, while updates go ahead without asking. \
+                     Unlike --confirm, when stdout isn't a terminal, e.g. in CI, no branch is \
+                     deleted.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("update-submodules")
+                .long("update-submodules")
+                .help("Run `git submodule update --init --recursive` after updating branches")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("set-head")
+                .long("set-head")
+                .help("Set <remote>/HEAD from the remote when it is missing")
+                .long_help(
+                    "Set <remote>/HEAD from the remote when it is missing, by running \
+                     `git remote set-head <remote> --auto`. Repositories that weren't cloned \
+                     lack this ref, which leaves git-up guessing the default branch.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("fail-fast")
+                .long("fail-fast")
+                .help("Stop at the first branch that fails to sync")
+                .long_help(
+                    "Stop at the first branch that fails to sync. Without this flag the \
+                     remaining branches are still synced, but git-up exits with a non-zero \
+                     status if any branch failed.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Print progress information while syncing (-vv for debug output)")
+                .action(ArgAction::Count),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .help("Only print warnings and errors")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("warnings-as-errors")
+                .long("warnings-as-errors")
+                .help("Exit with an error if any branch has a warning, e.g. with --dry-run in CI")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("fail-on-protected")
+                .long("fail-on-protected")
+                .help("Exit with an error if a protected branch was kept from being deleted")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("digest")
+                .long("digest")
+                .help("Before syncing, list the commits new on the default branch's upstream")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("timings")
+                .long("timings")
+                .help("Print how long fetching and syncing branches took to stderr")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("group")
+                .long("group")
+                .help("Print branches at the end, grouped by what happened and sorted by name")
+                .long_help(
+                    "Print branches once they've all been processed instead of as each one \
+                     finishes: first updates, then deletions, then warnings, then anything \
+                     kept or failed, each sorted by branch name.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("show-stat")
+                .long("show-stat")
+                .help("Print a diffstat of what each fast-forward brought in")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("abbrev")
+                .long("abbrev")
+                .value_name("N")
+                .help("Abbreviate shas in messages to N characters [default: 7]")
+                .value_parser(clap::value_parser!(u8).range(4..=64)),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("TEMPLATE")
+                .help("Print each branch using TEMPLATE, e.g. '{branch}: {old} -> {new}'")
+                .long_help(
+                    "Print each branch using TEMPLATE, e.g. '{branch}: {old} -> {new}'. The \
+                     placeholders are {action}, {branch}, {old}, {new}, {ahead}, {behind}, \
+                     {reason} and {detail}; {ahead} and {behind} count the commits only on the \
+                     old and new tips. Write {{ and }} for literal braces. The default, \
+                     '{action} {branch}{detail}', is git-up's usual output.",
+                )
+                .value_parser(Template::parse)
+                .conflicts_with_all(["json", "porcelain"]),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .value_name("N")
+                .help(
+                    "Number of branches to process concurrently. The current branch always \
+                     runs first, on its own",
+                )
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .help("Print a JSON summary of every branch instead of human-readable output")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("porcelain")
+                .long("porcelain")
+                .value_name("VERSION")
+                .help("Print one stable, space-separated line per branch instead (only v1 so far)")
+                .long_help(
+                    "Print one line per branch instead of human-readable output: \
+                     `<action> <branch> <old-sha> <new-sha>`, with `-` for a missing sha. \
+                     The action is one of updated, deleted, warn, noop, skip, or error. The \
+                     v1 format won't change.",
+                )
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("v1")
+                .value_parser(PossibleValuesParser::new(["v1"]))
+                .conflicts_with_all(["json", "plan", "interactive"]),
+        )
+        .arg(
+            Arg::new("color")
+                .long("color")
+                .value_name("WHEN")
+                .help(
+                    "When to color the output; auto honors NO_COLOR and disables color when piped",
+                )
+                .value_parser(PossibleValuesParser::new(["auto", "always", "never"]))
+                .default_value("auto"),
+        )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .help("How to write log messages to stderr; ndjson writes one JSON object per line")
+                .long_help(
+                    "How to write log messages to stderr. ndjson writes one JSON object per \
+                     line with a timestamp, level, and message, and logs progress by default.",
+                )
+                .value_parser(PossibleValuesParser::new(["text", "ndjson"]))
+                .default_value("text"),
+        )
+}
+
+// a whole number followed by h (hours), d (days), or w (weeks)
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let unit = match value.chars().last() {
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        Some('w') => 7 * 24 * 60 * 60,
+        _ => return Err("expected a number followed by h, d, or w".to_string()),
+    };
+    let count: u64 = value[..value.len() - 1]
+        .parse()
+        .map_err(|_| format!("`{}` isn't a whole number", &value[..value.len() - 1]))?;
+    count
+        .checked_mul(unit)
+        .map(Duration::from_secs)
+        .ok_or_else(|| "duration is too large".to_string())
+}
+
+fn parse_days(value: &str) -> Result<Duration, String> {
+    let days: u64 = value
+        .parse()
+        .map_err(|_| format!("`{}` isn't a whole number", value))?;
+    days.checked_mul(24 * 60 * 60)
+        .map(Duration::from_secs)
+        .ok_or_else(|| "duration is too large".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_days() {
+        assert_eq!(parse_days("2"), Ok(Duration::from_secs(2 * 24 * 60 * 60)));
+        assert!(parse_days("2d").is_err());
+        assert_eq!(
+            parse_days(&u64::MAX.to_string()),
+            Err("duration is too large".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("12h"), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(
+            parse_duration("2w"),
+            Ok(Duration::from_secs(14 * 24 * 60 * 60))
+        );
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("1.5d").is_err());
+        assert_eq!(
+            parse_duration(&format!("{}w", u64::MAX)),
+            Err("duration is too large".to_string())
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use clap::{Arg, Command};
+
+// completion scripts are built from the same argument definitions as --help, so new flags
+// and subcommands are picked up without touching this file. branch names aren't completed.
+pub fn generate(shell: &str, mut command: Command) -> String {
+    command.build();
+    let name = command.get_name().to_string();
+    let args = options(&command);
+    // each visible subcommand with its description and flags, e.g. sync and undo
+    let subcommands: Vec<(&str, String, Vec<&Arg>)> = command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .map(|subcommand| {
+            let about = subcommand
+                .get_about()
+                .map(|about| about.to_string())
+                .unwrap_or_default();
+            (subcommand.get_name(), about, options(subcommand))
+        })
+        .collect();
+
+    match shell {
+        "bash" => bash(&name, &args, &subcommands),
+        "zsh" => zsh(&name, &args, &subcommands),
+        _ => fish(&name, &args, &subcommands),
+    }
+}
+
+fn options(command: &Command) -> Vec<&Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+        .collect()
+}
+
+fn bash(name: &str, args: &[&Arg], subcommands: &[(&str, String, Vec<&Arg>)]) -> String {
+    let function = format!("_{}", name.replace('-', "_"));
+    let names: Vec<&str> = subcommands.iter().map(|(name, ..)| *name).collect();
+    let mut cases = String::new();
+    for (subcommand, _, args) in subcommands {
+        cases.push_str(&format!("        {})\n{}", subcommand, bash_options(args)));
+    }
+    cases.push_str(&format!("        *)\n{}", bash_options(args)));
+
+    // the first word that names a subcommand picks which flags are offered; before one is
+    // given, words that aren't flags complete to subcommand names
+    format!(
+        "{function}() {{
+    local cur prev word subcommand options
+    COMPREPLY=()
+    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"
+    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"
+
+    for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do
+        case \"${{word}}\" in
+            {pattern})
+                subcommand=\"${{word}}\"
+                break
+                ;;
+        esac
+    done
+
+    case \"${{subcommand}}\" in
+{cases}    esac
+
+    if [[ \"${{cur}}\" == -* ]]; then
+        COMPREPLY=( $(compgen -W \"${{options}}\" -- \"${{cur}}\") )
+    elif [[ -z \"${{subcommand}}\" ]]; then
+        COMPREPLY=( $(compgen -W \"{names}\" -- \"${{cur}}\") )
+    fi
+}}
+
+complete -F {function} {name}
+",
+        pattern = names.join("|"),
+        names = names.join(" "),
+    )
+}
+
+// one arm of the subcommand case: completes the value of the previous flag, or sets the
+// flags to offer
+fn bash_options(args: &[&Arg]) -> String {
+    let mut options = Vec::new();
+    let mut values = String::new();
+    for arg in args {
+        let flags = flags(arg);
+        if arg.get_action().takes_values() {
+            // values without a fixed set of choices are left for the user to type
+            values.push_str(&format!(
+                "                {})\n                    COMPREPLY=( $(compgen -W \"{}\" -- \"${{cur}}\") )\n                    return 0\n                    ;;\n",
+                flags.join("|"),
+                possible_values(arg).join(" "),
+            ));
+        }
+        options.extend(flags);
+    }
+    if values.is_empty() {
+        return format!(
+            "            options=\"{}\"\n            ;;\n",
+            options.join(" ")
+        );
+    }
+    format!(
+        "            case \"${{prev}}\" in\n{}            esac\n            options=\"{}\"\n            ;;\n",
+        values,
+        options.join(" "),
+    )
+}
+
+fn zsh(name: &str, args: &[&Arg], subcommands: &[(&str, String, Vec<&Arg>)]) -> String {
+    let commands: Vec<String> = subcommands
+        .iter()
+        .map(|(subcommand, about, _)| {
+            format!(
+                "'{}:{}'",
+                subcommand,
+                about.replace('\'', "'\\''").replace(':', "\\:")
+            )
+        })
+        .collect();
+    let mut cases = String::new();
+    for (subcommand, _, args) in subcommands {
+        cases.push_str(&format!(
+            "                {})\n                    _arguments -s \\\n                        {}\n                    ;;\n",
+            subcommand,
+            zsh_specs(args).join(" \\\n                        "),
+        ));
+    }
+
+    format!(
+        "#compdef {name}
+
+_{name}() {{
+    local curcontext=\"$curcontext\" state line
+    local -a commands
+    commands=({commands})
+
+    _arguments -s -C \\
+        {specs} \\
+        '1: :->command' \\
+        '*:: :->args'
+
+    case $state in
+        command)
+            _describe 'command' commands
+            ;;
+        args)
+            case $line[1] in
+{cases}            esac
+            ;;
+    esac
+}}
+
+_{name} \"$@\"
+",
+        commands = commands.join(" "),
+        specs = zsh_specs(args).join(" \\\n        "),
+    )
+}
+
+fn zsh_specs(args: &[&Arg]) -> Vec<String> {
+    let mut specs = Vec::new();
+    for arg in args {
+        let help = zsh_escape(&help(arg));
+        let repeatable = if matches!(
+            arg.get_action(),
+            clap::ArgAction::Count | clap::ArgAction::Append
+        ) {
+            "*"
+        } else {
+            ""
+        };
+        let value = if arg.get_action().takes_values() {
+            let value_name = arg
+                .get_value_names()
+                .and_then(|names| names.first())
+                .map(|name| name.to_string())
+                .unwrap_or_default();
+            let values = possible_values(arg);
+            if values.is_empty() {
+                format!(":{}:", value_name)
+            } else {
+                format!(":{}:({})", value_name, values.join(" "))
+            }
+        } else {
+            String::new()
+        };
+        for flag in flags(arg) {
+            specs.push(format!("'{}{}[{}]{}'", repeatable, flag, help, value));
+        }
+    }
+    specs
+}
+
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn fish(name: &str, args: &[&Arg], subcommands: &[(&str, String, Vec<&Arg>)]) -> String {
+    let mut lines = vec![format!("complete -c {} -f", name)];
+    // top-level flags and subcommand names are only offered before a subcommand is given
+    lines.extend(fish_options(name, "__fish_use_subcommand", args));
+    for (subcommand, about, _) in subcommands {
+        lines.push(format!(
+            "complete -c {} -n __fish_use_subcommand -a {} -d '{}'",
+            name,
+            subcommand,
+            about.replace('\'', "\\'")
+        ));
+    }
+    for (subcommand, _, args) in subcommands {
+        let condition = format!("'__fish_seen_subcommand_from {}'", subcommand);
+        lines.extend(fish_options(name, &condition, args));
+    }
+    lines.join("\n") + "\n"
+}
+
+fn fish_options(name: &str, condition: &str, args: &[&Arg]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for arg in args {
+        let mut line = format!("complete -c {} -n {}", name, condition);
+        if let Some(short) = arg.get_short() {
+            line.push_str(&format!(" -s {}", short));
+        }
+        if let Some(long) = arg.get_long() {
+            line.push_str(&format!(" -l {}", long));
+        }
+        line.push_str(&format!(" -d '{}'", help(arg).replace('\'', "\\'")));
+        if arg.get_action().takes_values() {
+            line.push_str(" -x");
+            let values = possible_values(arg);
+            if !values.is_empty() {
+                line.push_str(&format!(" -a '{}'", values.join(" ")));
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+fn flags(arg: &Arg) -> Vec<String> {
+    arg.get_short()
+        .map(|short| format!("-{}", short))
+        .into_iter()
+        .chain(arg.get_long().map(|long| format!("--{}", long)))
+        .collect()
+}
+
+fn help(arg: &Arg) -> String {
+    arg.get_help()
+        .map(|help| help.to_string())
+        .unwrap_or_default()
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
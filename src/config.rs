@@ -0,0 +1,158 @@
+use std::fs;
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, warn};
+
+use crate::git;
+
+const CONFIG_FILE: &str = ".git-up.toml";
+
+// settings read from .git-up.toml at the root of the repository. command-line flags take
+// precedence over these, and a missing file is the same as an empty one.
+//
+//     remote = "upstream"
+//     protected = ["release/*", "develop"]
+//     rebase = true
+//     autostash = false
+#[derive(Default)]
+pub struct FileConfig {
+    pub remote: Option<String>,
+    pub protected: Vec<String>,
+    pub rebase: Option<bool>,
+    pub autostash: Option<bool>,
+}
+
+impl FileConfig {
+    pub fn load() -> Result<Self> {
+        let path = git::get_toplevel()?.join(CONFIG_FILE);
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                debug!("Loading config from {}", path.display());
+                Self::parse(&contents).with_context(|| format!("Failed to parse {}", CONFIG_FILE))
+            }
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    // supports the subset of TOML the settings above need: top-level keys with string,
+    // boolean, or single-line string array values
+    fn parse(contents: &str) -> Result<Self> {
+        let mut config = Self::default();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(anyhow!("line {}: expected `key = value`", index + 1))?;
+            let key = key.trim();
+            if !["remote", "protected", "rebase", "autostash"].contains(&key) {
+                warn!("Ignoring unknown setting `{}` in {}", key, CONFIG_FILE);
+                continue;
+            }
+            let value = Value::parse(value.trim()).ok_or(anyhow!(
+                "line {}: unsupported value `{}`",
+                index + 1,
+                value.trim()
+            ))?;
+
+            match (key, value) {
+                ("remote", Value::String(remote)) => config.remote = Some(remote),
+                ("protected", Value::Array(patterns)) => config.protected = patterns,
+                ("protected", Value::String(pattern)) => config.protected = vec![pattern],
+                ("rebase", Value::Bool(rebase)) => config.rebase = Some(rebase),
+                ("autostash", Value::Bool(autostash)) => config.autostash = Some(autostash),
+                (key, _) => {
+                    return Err(anyhow!("line {}: wrong type for `{}`", index + 1, key));
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+enum Value {
+    String(String),
+    Bool(bool),
+    Array(Vec<String>),
+}
+
+impl Value {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => {
+                if let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+                    items
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .map(parse_string)
+                        .collect::<Option<Vec<String>>>()
+                        .map(Value::Array)
+                } else {
+                    parse_string(value).map(Value::String)
+                }
+            }
+        }
+    }
+}
+
+fn parse_string(value: &str) -> Option<String> {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+        .map(String::from)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            ('#', None) => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_setting() {
+        let config = FileConfig::parse(
+            "# settings\n\
+             remote = \"upstream\" # trailing comment\n\
+             protected = ['release/*', \"develop\"]\n\
+             rebase = true\n\
+             autostash = false\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.remote.as_deref(), Some("upstream"));
+        assert_eq!(config.protected, ["release/*", "develop"]);
+        assert_eq!(config.rebase, Some(true));
+        assert_eq!(config.autostash, Some(false));
+    }
+
+    #[test]
+    fn ignores_unknown_keys() {
+        let config = FileConfig::parse("jobs = 4\nremote = \"origin\"").unwrap();
+        assert_eq!(config.remote.as_deref(), Some("origin"));
+    }
+
+    #[test]
+    fn rejects_wrong_types() {
+        assert!(FileConfig::parse("rebase = \"yes\"").is_err());
+        assert!(FileConfig::parse("remote").is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use colored::*;
+use git_up::{
+    git,
+    report::{BranchOutcome, BranchReport, SkipReason},
+};
+
+use crate::abbrev;
+
+// reproduces the built-in output: {action} is the leading phrase, e.g. "Updated branch", and
+// {detail} is everything after the branch name
+pub const DEFAULT_FORMAT: &str = "{action} {branch}{detail}";
+
+const FIELDS: &str = "{action}, {branch}, {old}, {new}, {ahead}, {behind}, {reason}, {detail}";
+
+#[derive(Clone, Copy)]
+enum Field {
+    Action,
+    Branch,
+    Old,
+    New,
+    Ahead,
+    Behind,
+    Reason,
+    Detail,
+}
+
+#[derive(Clone)]
+enum Piece {
+    Text(String),
+    Field(Field),
+}
+
+// a --format template, parsed once up front so a typo is reported before anything runs.
+// `{{` and `}}` stand for literal braces
+#[derive(Clone)]
+pub struct Template(Vec<Piece>);
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    if !closed {
+                        return Err("unclosed `{`; write `{{` for a literal brace".to_string());
+                    }
+                    let field = match name.as_str() {
+                        "action" => Field::Action,
+                        "branch" => Field::Branch,
+                        "old" => Field::Old,
+                        "new" => Field::New,
+                        "ahead" => Field::Ahead,
+                        "behind" => Field::Behind,
+                        "reason" => Field::Reason,
+                        "detail" => Field::Detail,
+                        _ => {
+                            return Err(format!(
+                                "unknown placeholder {{{}}}; expected one of {}",
+                                name, FIELDS
+                            ))
+                        }
+                    };
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Field(field));
+                }
+                '}' => return Err("unmatched `}`; write `}}` for a literal brace".to_string()),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Self(pieces))
+    }
+
+    // None for branches the output leaves out, e.g. ones already up to date. each placeholder is
+    // colored on its own, so text around it in the template is never colored
+    pub fn render(&self, report: &BranchReport, suffix: &str, len: usize) -> Option<String> {
+        let (action, color, detail) = describe(report, suffix, len)?;
+        let outcome = &report.outcome;
+        // counting commits runs git, so it's only done when the template asks for it
+        let counts = self
+            .0
+            .iter()
+            .any(|piece| matches!(piece, Piece::Field(Field::Ahead | Field::Behind)))
+            .then(|| match (outcome.old_sha(), outcome.new_sha()) {
+                (Some(old), Some(new)) => git::ahead_behind(old, new).ok(),
+                _ => None,
+            })
+            .flatten();
+        let count = |count: Option<usize>| count.map_or("-".to_string(), |count| count.to_string());
+
+        let mut line = String::new();
+        for piece in &self.0 {
+            match piece {
+                Piece::Text(text) => line.push_str(text),
+                Piece::Field(Field::Action) => {
+                    line.push_str(&action.color(color).to_string());
+                }
+                Piece::Field(Field::Branch) => {
+                    line.push_str(&format!(
+                        "{}{}",
+                        report.branch.color(color).bold(),
+                        "".clear()
+                    ));
+                }
+                Piece::Field(Field::Old) => {
+                    line.push_str(outcome.old_sha().map_or("-", |sha| abbrev(sha, len)));
+                }
+                Piece::Field(Field::New) => {
+                    line.push_str(outcome.new_sha().map_or("-", |sha| abbrev(sha, len)));
+                }
+                Piece::Field(Field::Ahead) => line.push_str(&count(counts.map(|(ahead, _)| ahead))),
+                Piece::Field(Field::Behind) => {
+                    line.push_str(&count(counts.map(|(_, behind)| behind)))
+                }
+                Piece::Field(Field::Reason) => {
+                    line.push_str(&outcome.reason().unwrap_or_default());
+                }
+                Piece::Field(Field::Detail) => line.push_str(&detail),
+            }
+        }
+        Some(line)
+    }
+}
+
+// the leading phrase, its color, and the rest of the built-in line for a branch
+fn describe(report: &BranchReport, suffix: &str, len: usize) -> Option<(String, Color, String)> {
+    let described = match &report.outcome {
+        BranchOutcome::UpToDate { .. }
+        | BranchOutcome::Skipped {
+            reason: SkipReason::NoRemoteBranch | SkipReason::Inactive | SkipReason::DeleteOnly,
+            ..
+        } => return None,
+        BranchOutcome::Updated { old, .. } => (
+            "Updated branch".to_string(),
+            Color::Green,
+            format!(" (was {}).{}", abbrev(old, len), suffix),
+        ),
+        BranchOutcome::Rebased { old, onto, .. } => (
+            "Rebased branch".to_string(),
+            Color::Green,
+            format!(" onto {} (was {}).{}", onto, abbrev(old, len), suffix),
+        ),
+        BranchOutcome::Merged { old, from, .. } => (
+            format!("Merged {} into branch", from),
+            Color::Green,
+            format!(" (was {}).{}", abbrev(old, len), suffix),
+        ),
+        BranchOutcome::Pulled { old, .. } => (
+            "Pulled branch".to_string(),
+            Color::Green,
+            format!(" (was {}).{}", abbrev(old, len), suffix),
+        ),
+        BranchOutcome::Deleted { old } => (
+            "Deleted branch".to_string(),
+            Color::Red,
+            format!(" (was {}).{}", abbrev(old, len), suffix),
+        ),
+        BranchOutcome::Warned { reason, .. } => (
+            "Warning:".to_string(),
+            Color::Yellow,
+            format!(" {}", reason),
+        ),
+        BranchOutcome::Skipped {
+            reason: SkipReason::Protected,
+            ..
+        } => (
+            "Kept protected branch".to_string(),
+            Color::Cyan,
+            " (upstream is gone, but the branch is protected).".to_string(),
+        ),
+        BranchOutcome::Skipped {
+            reason: SkipReason::PreDeleteHook,
+            ..
+        } => (
+            "Kept branch".to_string(),
+            Color::Yellow,
+            " (the pre-delete hook failed).".to_string(),
+        ),
+        BranchOutcome::Skipped {
+            reason: SkipReason::BackupFailed(remote),
+            ..
+        } => (
+            "Kept branch".to_string(),
+            Color::Yellow,
+            format!(" (pushing it to {} failed).", remote),
+        ),
+        BranchOutcome::Skipped {
+            reason: SkipReason::NotConfirmed,
+            ..
+        } => ("Kept branch".to_string(), Color::Cyan, ".".to_string()),
+        BranchOutcome::Skipped {
+            reason: reason @ SkipReason::LinkedWorktree(_),
+            ..
+        } => (
+            "Warning:".to_string(),
+            Color::Yellow,
+            format!(" skipped ({})", reason),
+        ),
+        BranchOutcome::Failed(e) => (
+            "Error:".to_string(),
+            Color::Red,
+            format!(" failed to process branch: {}", e),
+        ),
+    };
+    Some(described)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn updated() -> BranchReport {
+        BranchReport {
+            branch: "feature".to_string(),
+            outcome: BranchOutcome::Updated {
+                old: "1234567890".to_string(),
+                new: "abcdef0123".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        colored::control::set_override(false);
+        let template = Template::parse("{branch}: {old} -> {new} {{{reason}}}").unwrap();
+        assert_eq!(
+            template.render(&updated(), "", 7).unwrap(),
+            "feature: 1234567 -> abcdef0 {}"
+        );
+        let default = Template::parse(DEFAULT_FORMAT).unwrap();
+        assert_eq!(
+            default.render(&updated(), " (dry-run)", 4).unwrap(),
+            "Updated branch feature (was 1234). (dry-run)"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        assert!(Template::parse("{sha}").is_err());
+        assert!(Template::parse("{branch").is_err());
+        assert!(Template::parse("branch}").is_err());
+    }
+}
@@ -1,29 +1,160 @@
 use anyhow::{anyhow, Context, Result};
 use log::debug;
 use std::{
-    io::BufRead,
-    path::Path,
-    process::{Command, Output},
+    collections::HashMap,
+    env, io,
+    path::{Path, PathBuf},
+    process::{self, Command, Output, Stdio},
+    sync::RwLock,
+    thread,
+    time::{Duration, Instant},
 };
 use tap::{Tap, TapFallible};
 
-pub fn delete_branch(local_branch: &str) -> Result<()> {
-    let result = Command::new("git")
+use crate::interrupt;
+
+// set from -C; every git command runs there instead of in the current directory
+static WORK_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+pub fn set_work_dir(work_dir: Option<&Path>) {
+    if let Ok(mut current) = WORK_DIR.write() {
+        *current = work_dir.map(Path::to_path_buf);
+    }
+}
+
+fn work_dir() -> Option<PathBuf> {
+    WORK_DIR.read().ok().and_then(|work_dir| work_dir.clone())
+}
+
+fn git() -> Command {
+    let mut command = Command::new("git");
+    // output is always captured or passed through, so a pager would only wait for input that
+    // never comes
+    command.env("GIT_PAGER", "cat");
+    if let Some(work_dir) = work_dir() {
+        command.current_dir(work_dir);
+    }
+    command
+}
+
+// for commands that only read. GIT_OPTIONAL_LOCKS=0 stops them from taking the index lock to
+// refresh stat info, so they don't contend with other git processes
+fn git_read_only() -> Command {
+    let mut command = git();
+    command.env("GIT_OPTIONAL_LOCKS", "0");
+    command
+}
+
+// {branch} is passed to the shell as an argument rather than pasted into the command, since
+// branch names can contain characters the shell would interpret
+pub fn run_hook(command: &str, branch: &str) -> Result<bool> {
+    debug!("Running hook: {}", command);
+    let mut shell = Command::new("sh");
+    shell
+        .arg("-c")
+        .arg(substitute_branch(command))
+        .arg("git-up-hook")
+        .arg(branch)
+        // stdout is reserved for git-up's results
+        .stdout(io::stderr());
+    if let Some(work_dir) = work_dir() {
+        shell.current_dir(work_dir);
+    }
+    let status = shell.status().with_context(|| "Failed to execute hook")?;
+    Ok(status.success())
+}
+
+// swaps {branch} for a reference to $1 that stays one word wherever the placeholder sits:
+// "$1" outside quotes, $1 within double quotes, and '"$1"' to step out of single quotes
+fn substitute_branch(command: &str) -> String {
+    let mut substituted = String::with_capacity(command.len());
+    let mut quote = None;
+    let mut rest = command;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("{branch}") {
+            substituted.push_str(match quote {
+                None => "\"$1\"",
+                Some('"') => "$1",
+                _ => "'\"$1\"'",
+            });
+            rest = after;
+            continue;
+        }
+        match (c, quote) {
+            // a backslash keeps the next character from opening or closing a quote
+            ('\\', None | Some('"')) => {
+                let escaped: String = rest.chars().take(2).collect();
+                substituted.push_str(&escaped);
+                rest = &rest[escaped.len()..];
+                continue;
+            }
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(open)) if c == open => quote = None,
+            _ => {}
+        }
+        substituted.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    substituted
+}
+
+pub fn delete_branch(local_branch: &str, force: bool) -> Result<()> {
+    backend().delete_branch(local_branch, force)
+}
+
+// `git branch -d` for a branch that HEAD doesn't contain, e.g. because another branch is
+// checked out or the default branch hasn't caught up yet. git's merge check runs in a
+// throwaway worktree detached at `merged_into`, so it compares against that and the user's
+// checkout is left alone
+pub fn delete_merged_branch(local_branch: &str, merged_into: &str) -> Result<()> {
+    let worktree_path = env::temp_dir().join(format!(
+        "git-up-delete-{}-{}",
+        process::id(),
+        local_branch.replace('/', "-")
+    ));
+
+    let result = git()
+        .arg("worktree")
+        .arg("add")
+        .arg("--quiet")
+        .arg("--detach")
+        .arg("--no-checkout")
+        .arg(&worktree_path)
+        .arg(merged_into)
+        .run_for_output()?;
+    if !result.status.success() {
+        return Err(anyhow!("Failed to create a worktree for deleting"));
+    }
+
+    let deleted = git()
+        .arg("-C")
+        .arg(&worktree_path)
         .arg("branch")
-        .arg("-D")
+        .arg("-d")
         .arg("--quiet")
         .arg(local_branch)
+        .run_for_output();
+
+    git()
+        .arg("worktree")
+        .arg("remove")
+        .arg("--force")
+        .arg(&worktree_path)
         .run_for_output()?;
 
-    if result.status.success() {
+    if deleted?.status.success() {
         Ok(())
     } else {
         Err(anyhow!("Failed to delete branch"))
     }
 }
 
+pub fn create_branch(local_branch: &str, sha: &str) -> Result<()> {
+    backend().create_branch(local_branch, sha)
+}
+
 pub fn checkout(branch: &str) -> Result<()> {
-    let result = Command::new("git")
+    let result = git()
         .arg("checkout")
         .arg("--quiet")
         .arg(branch)
@@ -36,32 +167,284 @@ pub fn checkout(branch: &str) -> Result<()> {
     }
 }
 
-pub fn update_ref(full_branch: &str, remote_branch: &str) -> Result<()> {
-    let result = Command::new("git")
+// keeps the branch's commits reachable from refs/git-up/archive/; the caller deletes it
+pub fn archive_branch(local_branch: &str) -> Result<()> {
+    let result = git()
         .arg("update-ref")
-        .arg(full_branch)
-        .arg(remote_branch)
+        .arg(format!("refs/git-up/archive/{}", local_branch))
+        .arg(format!("refs/heads/{}", local_branch))
         .run_for_output()?;
 
     if result.status.success() {
         Ok(())
     } else {
-        Err(anyhow!("Failed to update ref"))
+        Err(anyhow!("Failed to archive branch"))
+    }
+}
+
+pub fn update_ref(full_branch: &str, remote_branch: &str) -> Result<()> {
+    backend().update_ref(full_branch, remote_branch)
+}
+
+pub fn fast_forward_merge(branch: &str, worktree_path: Option<&str>) -> Result<()> {
+    backend().fast_forward_merge(branch, worktree_path)
+}
+
+// pulls the current branch, leaving merge vs. rebase up to the user's pull config, and
+// returns the new HEAD. a pull that stops on conflicts is aborted.
+pub fn pull(autostash: bool) -> Result<String> {
+    let result = git()
+        .arg("pull")
+        .arg("--quiet")
+        .arg("--no-edit")
+        .tap_mut(|command| {
+            if autostash {
+                command.arg("--autostash");
+            }
+        })
+        .run_with_prompts()?;
+
+    if result.status.success() {
+        resolve("HEAD").ok_or(anyhow!("Failed to resolve HEAD"))
+    } else {
+        // don't leave the working tree mid-merge or mid-rebase
+        if let Some(operation @ ("merge" | "rebase")) = in_progress_operation() {
+            git().arg(operation).arg("--abort").run_for_output()?;
+        }
+        Err(anyhow!("Failed to pull"))
     }
 }
 
-pub fn fast_forward_merge(branch: &str) -> Result<()> {
-    let result = Command::new("git")
+// merges `branch` into the current branch and returns the new HEAD. a merge that stops on
+// conflicts is aborted.
+pub fn merge(branch: &str, autostash: bool) -> Result<String> {
+    let result = git()
         .arg("merge")
-        .arg("--ff-only")
         .arg("--quiet")
+        .arg("--no-edit")
+        .tap_mut(|command| {
+            if autostash {
+                command.arg("--autostash");
+            }
+        })
         .arg(branch)
         .run_for_output()?;
 
+    if result.status.success() {
+        resolve("HEAD").ok_or(anyhow!("Failed to resolve HEAD"))
+    } else {
+        // don't leave the working tree mid-merge
+        if in_progress_operation() == Some("merge") {
+            git().arg("merge").arg("--abort").run_for_output()?;
+        }
+        Err(anyhow!("Failed to merge {}", branch))
+    }
+}
+
+pub fn rebase(onto: &str) -> Result<()> {
+    let result = git()
+        .arg("rebase")
+        .arg("--quiet")
+        .arg(onto)
+        .run_for_output()?;
+
+    if result.status.success() {
+        Ok(())
+    } else {
+        // don't leave the working tree mid-rebase
+        git().arg("rebase").arg("--abort").run_for_output()?;
+        Err(anyhow!("Failed to rebase onto {}", onto))
+    }
+}
+
+// rebases a branch that isn't checked out by replaying it in a temporary detached
+// worktree, so the main working tree is never touched. on conflict the rebase is
+// aborted and the branch is left where it was.
+pub fn rebase_detached(full_branch: &str, onto: &str) -> Result<String> {
+    let old_sha = resolve(full_branch).ok_or(anyhow!("Failed to resolve {}", full_branch))?;
+    let worktree_path = env::temp_dir().join(format!(
+        "git-up-rebase-{}-{}",
+        process::id(),
+        old_sha.get(..7).unwrap_or(&old_sha)
+    ));
+
+    let result = git()
+        .arg("worktree")
+        .arg("add")
+        .arg("--quiet")
+        .arg("--detach")
+        .arg(&worktree_path)
+        .arg(&old_sha)
+        .run_for_output()?;
+    if !result.status.success() {
+        return Err(anyhow!("Failed to create a worktree for rebasing"));
+    }
+
+    let rebased = git()
+        .arg("-C")
+        .arg(&worktree_path)
+        .arg("rebase")
+        .arg("--quiet")
+        .arg(onto)
+        .run_for_output()
+        .and_then(|result| {
+            if result.status.success() {
+                git()
+                    .arg("-C")
+                    .arg(&worktree_path)
+                    .arg("rev-parse")
+                    .arg("HEAD")
+                    .run_for_output()
+                    .and_then(output_string)
+                    .map(|sha| sha.trim().to_string())
+            } else {
+                git()
+                    .arg("-C")
+                    .arg(&worktree_path)
+                    .arg("rebase")
+                    .arg("--abort")
+                    .run_for_output()?;
+                Err(anyhow!("Failed to rebase onto {}", onto))
+            }
+        });
+
+    git()
+        .arg("worktree")
+        .arg("remove")
+        .arg("--force")
+        .arg(&worktree_path)
+        .run_for_output()?;
+
+    let new_sha = rebased?;
+    let result = git()
+        .arg("update-ref")
+        .arg(full_branch)
+        .arg(&new_sha)
+        .arg(&old_sha)
+        .run_for_output()?;
+    if result.status.success() {
+        Ok(new_sha)
+    } else {
+        Err(anyhow!("Failed to update ref"))
+    }
+}
+
+// true when there are no merge commits between the merge base and the branch, which is
+// the only shape rebase_detached will attempt to replay
+pub fn is_linear_since_merge_base(branch: &str, upstream: &str) -> Result<bool> {
+    let merge_base = git_read_only()
+        .arg("merge-base")
+        .arg(upstream)
+        .arg(branch)
+        .run_for_output()?;
+    if !merge_base.status.success() {
+        return Ok(false);
+    }
+    let merge_base = output_string(merge_base)?.trim().to_string();
+
+    let result = git_read_only()
+        .arg("rev-list")
+        .arg("--min-parents=2")
+        .arg("--count")
+        .arg(format!("{}..{}", merge_base, branch))
+        .run_for_output()?;
+    if result.status.success() {
+        Ok(output_string(result)?.trim() == "0")
+    } else {
+        Err(anyhow!("Failed to inspect history of {}", branch))
+    }
+}
+
+// untracked files are left out, since a fast-forward only refuses to overwrite the ones it
+// would create and reports that on its own
+pub fn has_uncommitted_changes() -> Result<bool> {
+    let result = git_read_only()
+        .arg("status")
+        .arg("--porcelain")
+        .arg("--untracked-files=no")
+        .run_for_output()?;
+
+    if result.status.success() {
+        Ok(!result.stdout.is_empty())
+    } else {
+        Err(anyhow!("Failed to check the working tree for changes"))
+    }
+}
+
+// returns whether anything was stashed, since `git stash push` succeeds without
+// creating an entry when the working tree is clean
+pub fn stash_push() -> Result<bool> {
+    let before = resolve("refs/stash");
+    let result = git()
+        .arg("stash")
+        .arg("push")
+        .arg("--quiet")
+        .run_for_output()?;
+
+    if result.status.success() {
+        Ok(resolve("refs/stash") != before)
+    } else {
+        Err(anyhow!("Failed to stash changes"))
+    }
+}
+
+pub fn stash_pop() -> Result<()> {
+    let result = git()
+        .arg("stash")
+        .arg("pop")
+        .arg("--quiet")
+        .run_for_output()?;
+
     if result.status.success() {
         Ok(())
     } else {
-        Err(anyhow!("Failed to fast forward merge ref"))
+        Err(anyhow!("Failed to pop stashed changes"))
+    }
+}
+
+// unlike has_file, this also finds refs that have been packed into packed-refs
+pub fn has_ref(name: &str) -> bool {
+    resolve(name).is_some()
+}
+
+fn resolve(name: &str) -> Option<String> {
+    backend().resolve(name)
+}
+
+// asks the remote for its default branch and points refs/remotes/<remote>/HEAD at it. git's
+// own error, if any, has already been shown on stderr
+pub fn set_remote_head(remote: &str) -> Result<()> {
+    let result = git()
+        .arg("remote")
+        .arg("set-head")
+        .arg(remote)
+        .arg("--auto")
+        .run_with_prompts()?;
+
+    if result.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("git remote set-head exited with {}", result.status))
+    }
+}
+
+// submodules may be fetched from remotes of their own, which can ask for credentials too
+pub fn update_submodules() -> Result<()> {
+    let result = git()
+        .arg("submodule")
+        .arg("update")
+        .arg("--init")
+        .arg("--recursive")
+        .arg("--quiet")
+        .run_with_prompts()?;
+
+    if result.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "git submodule update exited with {}",
+            result.status
+        ))
     }
 }
 
@@ -82,90 +465,313 @@ impl Range {
     pub fn is_ancestor(&self) -> bool {
         is_ancestor(&self.a, &self.b)
     }
+
+    pub fn is_descendant(&self) -> bool {
+        is_ancestor(&self.b, &self.a)
+    }
+
+    // false when `a` and `b` have no commit in common, e.g. one was created with --orphan
+    pub fn has_common_history(&self) -> bool {
+        git_read_only()
+            .arg("merge-base")
+            .arg(&self.a)
+            .arg(&self.b)
+            .run_for_output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    // the commits on `b` that aren't on `a`, newest first, as `git log --oneline` prints them
+    pub fn commits(&self) -> Result<Vec<String>> {
+        let result = git_read_only()
+            .arg("log")
+            .arg("--oneline")
+            .arg("--no-decorate")
+            .arg(format!("{}..{}", self.a, self.b))
+            .run_for_output()?;
+
+        if result.status.success() {
+            output_lines(result)
+        } else {
+            Err(anyhow!("Failed to list commits in {}..{}", self.a, self.b))
+        }
+    }
 }
 
 fn is_ancestor(a: &str, b: &str) -> bool {
-    let result = Command::new("git")
+    backend().is_ancestor(a, b)
+}
+
+// a squash merge never makes the branch an ancestor of upstream, so instead build a
+// throwaway commit with the branch's tree on top of the merge base and ask `git cherry`
+// whether an equivalent patch already landed upstream
+pub fn is_squash_merged(branch: &str, upstream: &str) -> Result<bool> {
+    let merge_base = git_read_only()
         .arg("merge-base")
-        .arg("--is-ancestor")
-        .arg(a)
-        .arg(b)
-        .run_for_output();
+        .arg(upstream)
+        .arg(branch)
+        .run_for_output()?;
+    if !merge_base.status.success() {
+        return Ok(false);
+    }
+    let merge_base = output_string(merge_base)?.trim().to_string();
 
-    match result {
-        Ok(output) => output.status.success(),
-        Err(_) => false,
+    // a branch that changes nothing relative to the merge base has no content that could
+    // have landed upstream
+    let tree = format!("{}^{{tree}}", branch);
+    if resolve(&tree) == resolve(&format!("{}^{{tree}}", merge_base)) {
+        return Ok(false);
+    }
+
+    let squashed = git()
+        .env("GIT_AUTHOR_NAME", "git-up")
+        .env("GIT_AUTHOR_EMAIL", "git-up@localhost")
+        .env("GIT_COMMITTER_NAME", "git-up")
+        .env("GIT_COMMITTER_EMAIL", "git-up@localhost")
+        .arg("commit-tree")
+        .arg(&tree)
+        .arg("-p")
+        .arg(&merge_base)
+        .arg("-m")
+        .arg("git-up squash check")
+        .run_for_output()?;
+    if !squashed.status.success() {
+        return Err(anyhow!("Failed to create squash commit for {}", branch));
+    }
+    let squashed = output_string(squashed)?.trim().to_string();
+
+    let cherry = git_read_only()
+        .arg("cherry")
+        .arg(upstream)
+        .arg(&squashed)
+        .run_for_output()?;
+    if cherry.status.success() {
+        Ok(output_lines(cherry)?
+            .iter()
+            .all(|line| line.starts_with('-')))
+    } else {
+        Err(anyhow!("Failed to compare {} with {}", branch, upstream))
     }
 }
 
-pub fn make_range(a: &str, b: &str) -> Result<Range> {
-    let result = Command::new("git")
-        .arg("rev-parse")
-        .arg("--quiet")
-        .arg(a)
-        .arg(b)
+pub fn diff_stat(old: &str, new: &str) -> Result<String> {
+    let result = git_read_only()
+        .arg("diff")
+        .arg("--stat")
+        .arg(format!("{}..{}", old, new))
         .run_for_output()?;
 
-    let lines = output_lines(result);
-    if lines.len() != 2 {
-        return Err(anyhow!(
-            "Can't parse range {}..{}; Expected 2 lines, got {}",
-            a,
-            b,
-            lines.len()
-        ));
+    if result.status.success() {
+        output_string(result)
+    } else {
+        Err(anyhow!("Failed to diff {} and {}", old, new))
     }
+}
 
-    Ok(Range::new(lines[0].clone(), lines[1].clone()))
+pub fn ahead_behind(local: &str, remote: &str) -> Result<(usize, usize)> {
+    backend().ahead_behind(local, remote)
 }
 
-fn output_lines(output: std::process::Output) -> Vec<String> {
-    output.stdout.lines().map(|line| line.unwrap()).collect()
+// a snapshot of every local and remote-tracking ref, gathered with a single
+// for-each-ref so the branch loop doesn't spawn a rev-parse per branch
+#[derive(Clone, Default)]
+pub struct Refs {
+    shas: HashMap<String, String>,
+    committer_dates: HashMap<String, u64>,
+    upstreams: HashMap<String, String>,
 }
 
-pub fn has_file(path: &str) -> bool {
-    let result = Command::new("git")
-        .arg("rev-parse")
-        .arg("--quiet")
-        .arg("--git-path")
-        .arg(path)
-        .run_for_output();
+impl Refs {
+    pub fn sha(&self, refname: &str) -> Option<&String> {
+        self.shas.get(refname)
+    }
 
-    match result {
-        Ok(output) => {
-            if output.status.success() {
-                let file_path = String::from_utf8(output.stdout).unwrap();
-                Path::new(file_path.trim()).exists()
-            } else {
-                false
+    // seconds since the epoch that the commit at the tip of the ref was committed
+    pub fn committer_date(&self, refname: &str) -> Option<u64> {
+        self.committer_dates.get(refname).copied()
+    }
+
+    // the configured upstream of a branch, even if that upstream no longer exists
+    pub fn upstream(&self, full_branch: &str) -> Option<&String> {
+        self.upstreams.get(full_branch)
+    }
+
+    pub fn range(&self, a: &str, b: &str) -> Result<Range> {
+        match (self.sha(a), self.sha(b)) {
+            (Some(a), Some(b)) => Ok(Range::new(a.clone(), b.clone())),
+            _ => make_range(a, b),
+        }
+    }
+}
+
+pub fn get_refs() -> Result<Refs> {
+    let result = git_read_only()
+        .arg("for-each-ref")
+        .arg("--format")
+        .arg("%(refname) %(objectname) %(committerdate:unix) %(upstream)")
+        .arg("refs/heads")
+        .arg("refs/remotes")
+        .run_for_output()?;
+
+    if result.status.success() {
+        // $ git for-each-ref --format '%(refname) %(objectname) %(committerdate:unix) %(upstream)'
+        // refs/heads/main 5f3a1c2... 1714000000 refs/remotes/origin/main
+        // refs/heads/scratch 9b8e7d6... 1713000000
+        // refs/remotes/origin/main 5f3a1c2... 1714000000
+        let mut refs = Refs::default();
+        for line in output_lines(result)? {
+            let mut parts = line.split(' ');
+            if let (Some(refname), Some(sha)) = (parts.next(), parts.next()) {
+                refs.shas.insert(refname.to_string(), sha.to_string());
+                if let Some(date) = parts.next().and_then(|date| date.parse().ok()) {
+                    refs.committer_dates.insert(refname.to_string(), date);
+                }
+                if let Some(upstream) = parts.next().filter(|upstream| !upstream.is_empty()) {
+                    refs.upstreams
+                        .insert(refname.to_string(), upstream.to_string());
+                }
             }
         }
+        Ok(refs)
+    } else {
+        Err(anyhow!("Failed to list refs"))
+    }
+}
+
+pub fn make_range(a: &str, b: &str) -> Result<Range> {
+    let a_sha = resolve(a).ok_or(anyhow!("Can't resolve {}; the ref doesn't exist", a))?;
+    let b_sha = resolve(b).ok_or(anyhow!("Can't resolve {}; the ref doesn't exist", b))?;
+
+    Ok(Range::new(a_sha, b_sha))
+}
+
+fn output_lines(output: std::process::Output) -> Result<Vec<String>> {
+    Ok(output_string(output)?.lines().map(String::from).collect())
+}
+
+fn output_string(output: std::process::Output) -> Result<String> {
+    String::from_utf8(output.stdout).with_context(|| "git output was not valid UTF-8")
+}
+
+pub fn has_file(path: &str) -> bool {
+    match git_path(path) {
+        Ok(file_path) => file_path.exists(),
         Err(_) => false,
     }
 }
 
-pub fn symbolic_full_name(name: String) -> Option<String> {
-    let result = Command::new("git")
+pub fn is_inside_work_tree() -> bool {
+    match git_read_only()
         .arg("rev-parse")
-        .arg("--symbolic-full-name")
-        .arg(name)
-        .run_for_output();
-
-    match result {
+        .arg("--is-inside-work-tree")
+        .run_for_output()
+    {
         Ok(output) => {
-            if output.status.success() {
-                let stdout = String::from_utf8(output.stdout).unwrap();
-                Some(stdout.trim().to_string())
-            } else {
-                None
-            }
+            output.status.success() && output_string(output).is_ok_and(|s| s.trim() == "true")
         }
-        Err(_) => None,
+        Err(_) => false,
+    }
+}
+
+// the operation, if any, that is waiting on the user to finish or abort it
+pub fn in_progress_operation() -> Option<&'static str> {
+    [
+        ("rebase-merge", "rebase"),
+        ("rebase-apply", "rebase"),
+        ("MERGE_HEAD", "merge"),
+        ("CHERRY_PICK_HEAD", "cherry-pick"),
+        ("REVERT_HEAD", "revert"),
+    ]
+    .iter()
+    .find(|(path, _)| has_file(path))
+    .map(|(_, operation)| *operation)
+}
+
+pub fn get_toplevel() -> Result<PathBuf> {
+    let result = git_read_only()
+        .arg("rev-parse")
+        .arg("--show-toplevel")
+        .run_for_output()?;
+
+    if result.status.success() {
+        Ok(PathBuf::from(output_string(result)?.trim()))
+    } else {
+        Err(anyhow!("Failed to find the top level of the repository"))
+    }
+}
+
+pub fn git_path(path: &str) -> Result<PathBuf> {
+    let result = git_read_only()
+        .arg("rev-parse")
+        .arg("--quiet")
+        .arg("--git-path")
+        .arg(path)
+        .run_for_output()?;
+
+    if result.status.success() {
+        // relative paths are relative to the directory git ran in
+        let git_path = PathBuf::from(output_string(result)?.trim());
+        Ok(match work_dir() {
+            Some(work_dir) => work_dir.join(git_path),
+            None => git_path,
+        })
+    } else {
+        Err(anyhow!("Failed to resolve git path {}", path))
+    }
+}
+
+// maps each local tag to the object it points at
+pub fn get_tags() -> Result<HashMap<String, String>> {
+    let result = git_read_only()
+        .arg("for-each-ref")
+        .arg("--format")
+        .arg("%(refname:lstrip=2) %(objectname)")
+        .arg("refs/tags")
+        .run_for_output()?;
+
+    if result.status.success() {
+        Ok(output_lines(result)?
+            .iter()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(tag, sha)| (tag.to_string(), sha.to_string()))
+            .collect())
+    } else {
+        Err(anyhow!("Failed to get tags"))
+    }
+}
+
+pub fn get_remote_tags(remote: &str) -> Result<Vec<String>> {
+    let result = git_read_only()
+        .arg("ls-remote")
+        .arg("--tags")
+        .arg("--refs")
+        .arg(remote)
+        .run_with_prompts()?;
+
+    if result.status.success() {
+        // $ git ls-remote --tags --refs origin
+        // 5f3a1c2...	refs/tags/v1.0.0
+        Ok(output_lines(result)?
+            .iter()
+            .filter_map(|line| line.split_once("refs/tags/"))
+            .map(|(_, tag)| tag.to_string())
+            .collect())
+    } else {
+        Err(anyhow!("Failed to list tags on {}", remote))
+    }
+}
+
+pub fn delete_tag(tag: &str) -> Result<()> {
+    let result = git().arg("tag").arg("--delete").arg(tag).run_for_output()?;
+
+    if result.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("Failed to delete tag"))
     }
 }
 
 pub fn symbolic_ref(name: &str, short: bool) -> Option<String> {
-    let result = Command::new("git")
+    let result = git_read_only()
         .arg("symbolic-ref")
         .arg("--quiet")
         .tap_mut(|command| {
@@ -179,8 +785,9 @@ pub fn symbolic_ref(name: &str, short: bool) -> Option<String> {
     match result {
         Ok(output) => {
             if output.status.success() {
-                let stdout = String::from_utf8(output.stdout).unwrap();
-                Some(stdout.trim().to_string())
+                output_string(output)
+                    .ok()
+                    .map(|stdout| stdout.trim().to_string())
             } else {
                 None
             }
@@ -189,8 +796,9 @@ pub fn symbolic_ref(name: &str, short: bool) -> Option<String> {
     }
 }
 
-pub fn get_main_remote() -> Result<String> {
-    let result = Command::new("git")
+// returns None when no remote has a fetch URL
+pub fn get_main_remote() -> Result<Option<String>> {
+    let result = git_read_only()
         .arg("remote")
         .arg("--verbose")
         .run_for_output()?;
@@ -199,86 +807,491 @@ pub fn get_main_remote() -> Result<String> {
         // $ git remote --verbose
         // origin  git@github.com:samandmoore/git-up.git (fetch)
         // origin  git@github.com:samandmoore/git-up.git (push)
-        let lines = output_lines(result);
-        if !lines.is_empty() {
-            return Ok(lines[0].split_whitespace().next().unwrap().to_string());
-        }
-        Err(anyhow!("No remotes found"))
+        Ok(parse_main_remote(&output_lines(result)?))
+    } else {
+        Err(anyhow!("Failed to get remotes"))
+    }
+}
+
+fn parse_main_remote(lines: &[String]) -> Option<String> {
+    // remotes without a URL are listed without a "(fetch)" line
+    let remotes: Vec<&str> = lines
+        .iter()
+        .filter(|line| line.ends_with("(fetch)"))
+        .filter_map(|line| line.split_whitespace().next())
+        .collect();
+
+    // prefer "origin" over whichever remote git happens to list first
+    remotes
+        .iter()
+        .find(|remote| **remote == "origin")
+        .or(remotes.first())
+        .map(|remote| remote.to_string())
+}
+
+pub fn get_remote_url(remote: &str) -> Option<String> {
+    let result = git_read_only()
+        .arg("remote")
+        .arg("get-url")
+        .arg(remote)
+        .run_for_output()
+        .ok()?;
+
+    if result.status.success() {
+        output_string(result)
+            .ok()
+            .map(|stdout| stdout.trim().to_string())
     } else {
-        Err(anyhow!("No remotes found"))
+        None
     }
 }
 
-pub fn get_default_branch(remote: &str) -> Result<String> {
+pub fn get_remotes() -> Result<Vec<String>> {
+    let result = git_read_only().arg("remote").run_for_output()?;
+
+    if result.status.success() {
+        output_lines(result)
+    } else {
+        Err(anyhow!("Failed to get remotes"))
+    }
+}
+
+// `ask_remote` allows a round-trip to the remote when its HEAD isn't known locally
+pub fn get_default_branch(remote: &str, ask_remote: bool) -> Result<String> {
     // the ref/remotes/X/HEAD ref will always be missing if you didn't `git clone` the repository
-    symbolic_ref(&format!("refs/remotes/{}/HEAD", remote), false)
-        // if it is missing, we assume "main"
-        .unwrap_or(format!("refs/remotes/{}/main", remote))
+    let full_default_branch = match symbolic_ref(&format!("refs/remotes/{}/HEAD", remote), false) {
+        Some(full_default_branch) => full_default_branch,
+        None => match ask_remote.then(|| advertised_head(remote)).flatten() {
+            Some(branch) => {
+                debug!(
+                    "{}/HEAD is missing; {} advertises {}",
+                    remote, remote, branch
+                );
+                format!("refs/remotes/{}/{}", remote, branch)
+            }
+            None => {
+                // so guess from the usual names, falling back to "main" if none of them exist
+                let guess = ["main", "master", "trunk"]
+                    .iter()
+                    .map(|branch| format!("refs/remotes/{}/{}", remote, branch))
+                    .find(|full_branch| has_ref(full_branch))
+                    .unwrap_or(format!("refs/remotes/{}/main", remote));
+                debug!("{}/HEAD is missing; guessing {}", remote, guess);
+                guess
+            }
+        },
+    };
+    full_default_branch
         .strip_prefix(&format!("refs/remotes/{}/", remote))
         .map(|s| s.to_string())
         .ok_or(anyhow!("Failed to get default branch"))
 }
 
-pub fn fetch(remote: &str) -> Result<()> {
-    Command::new("git")
+// answers are kept by URL for the rest of the run, since each one costs a network round-trip
+static ADVERTISED_HEADS: RwLock<Option<HashMap<String, Option<String>>>> = RwLock::new(None);
+
+// the branch the remote's HEAD points at, as advertised by `git ls-remote --symref`
+fn advertised_head(remote: &str) -> Option<String> {
+    let url = get_remote_url(remote)?;
+    if let Some(head) = ADVERTISED_HEADS
+        .read()
+        .ok()
+        .and_then(|heads| heads.as_ref()?.get(&url).cloned())
+    {
+        return head;
+    }
+
+    let head = git_read_only()
+        .arg("ls-remote")
+        .arg("--symref")
+        .arg(remote)
+        .arg("HEAD")
+        .run_with_prompts()
+        .ok()
+        .filter(|result| result.status.success())
+        .and_then(|result| output_lines(result).ok())
+        .and_then(|lines| {
+            // $ git ls-remote --symref origin HEAD
+            // ref: refs/heads/main	HEAD
+            // 5f3a1c2...	HEAD
+            lines.iter().find_map(|line| {
+                line.strip_prefix("ref: refs/heads/")?
+                    .strip_suffix("\tHEAD")
+                    .map(String::from)
+            })
+        });
+    if let Ok(mut heads) = ADVERTISED_HEADS.write() {
+        heads
+            .get_or_insert_with(HashMap::new)
+            .insert(url, head.clone());
+    }
+    head
+}
+
+// used without a remote, so only local branches with the usual names are considered
+pub fn get_local_default_branch() -> Result<String> {
+    ["main", "master", "trunk"]
+        .iter()
+        .find(|branch| has_ref(&format!("refs/heads/{}", branch)))
+        .map(|branch| branch.to_string())
+        .ok_or(anyhow!(
+            "Can't find a local default branch; pass --default-branch"
+        ))
+}
+
+// asks the remote for just its HEAD, which is quick on a healthy connection. `--exit-code` is
+// left out so that an empty remote still counts as reachable
+pub fn check_reachable(remote: &str, timeout: Option<Duration>) -> Result<()> {
+    let mut command = git_read_only();
+    command
+        .arg("ls-remote")
+        .arg(remote)
+        .arg("HEAD")
+        .stdout(Stdio::null());
+    match timeout {
+        Some(timeout) => command.run_with_timeout(timeout),
+        None => command.run(),
+    }
+    .with_context(|| format!("Remote {} is unreachable", remote))
+}
+
+// extra args come after the defaults, so e.g. --no-progress overrides --progress
+pub fn fetch(remote: &str, timeout: Option<Duration>, args: &[String]) -> Result<()> {
+    let mut command = git();
+    command
         .arg("fetch")
         .arg("--prune")
         .arg("--quiet")
         .arg("--progress")
+        .args(args)
+        .arg(remote)
+        // stdout is reserved for git-up's results; progress goes to stderr along with
+        // anything printed by hooks on the remote
+        .stdout(io::stderr());
+    match timeout {
+        Some(timeout) => command.run_with_timeout(timeout),
+        None => command.run(),
+    }
+    .with_context(|| "Failed to execute git fetch command")
+}
+
+// pushes the branch to a branch of the same name on `remote`
+pub fn push(remote: &str, local_branch: &str) -> Result<()> {
+    git()
+        .arg("push")
+        .arg("--quiet")
         .arg(remote)
+        .arg(format!("refs/heads/{0}:refs/heads/{0}", local_branch))
+        // stdout is reserved for git-up's results
+        .stdout(io::stderr())
         .run()
-        .with_context(|| "Failed to execute git fetch command")
+        .with_context(|| format!("Failed to push {} to {}", local_branch, remote))
 }
 
 pub fn get_config(args: &[&str]) -> Result<Vec<String>> {
-    let result = Command::new("git")
-        .arg("config")
-        .args(args)
-        .run_for_output()?;
+    let result = git_read_only().arg("config").args(args).run_for_output()?;
 
     if result.status.success() {
-        Ok(output_lines(result))
+        output_lines(result)
+    } else if result.status.code() == Some(1) {
+        // git config exits with 1 when the key isn't set
+        Ok(Vec::new())
     } else {
         Err(anyhow!("Failed to get config"))
     }
 }
 
+// maps each local branch to its branch.<name>.remote setting
+pub fn get_branch_remotes() -> Result<HashMap<String, String>> {
+    let lines = get_config(&["--local", "--get-regexp", r"^branch\..*\.remote$"])?;
+    Ok(parse_branch_remotes(&lines))
+}
+
+// each line is the key and value separated by the first space, e.g.
+//
+//     branch.release.1.0.remote origin
+fn parse_branch_remotes(lines: &[String]) -> HashMap<String, String> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(' ')?;
+            let branch = key.strip_prefix("branch.")?.strip_suffix(".remote")?;
+            Some((branch.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+pub fn get_worktree_branches() -> Result<HashMap<String, String>> {
+    let result = git_read_only()
+        .arg("worktree")
+        .arg("list")
+        .arg("--porcelain")
+        .run_for_output()?;
+
+    if result.status.success() {
+        // $ git worktree list --porcelain
+        // worktree /path/to/git-up
+        // HEAD 5f3a1c2...
+        // branch refs/heads/main
+        //
+        // worktree /path/to/git-up-feature
+        // HEAD 9b8e7d6...
+        // detached
+        let mut worktree_branches = HashMap::new();
+        let mut worktree_path = None;
+        for line in output_lines(result)? {
+            if let Some(path) = line.strip_prefix("worktree ") {
+                worktree_path = Some(path.to_string());
+            } else if let Some(branch) = line.strip_prefix("branch refs/heads/") {
+                if let Some(path) = worktree_path.take() {
+                    worktree_branches.insert(branch.to_string(), path);
+                }
+            }
+        }
+        Ok(worktree_branches)
+    } else {
+        Err(anyhow!("Failed to list worktrees"))
+    }
+}
+
 pub fn get_branches() -> Result<Vec<String>> {
-    let result = Command::new("git")
-        .arg("branch")
-        .arg("--list")
+    // unlike `git branch --list`, for-each-ref never lists a "(HEAD detached at ...)" entry
+    let result = git_read_only()
+        .arg("for-each-ref")
         .arg("--format")
-        .arg("%(refname:short)")
+        .arg("%(refname:lstrip=2)")
+        .arg("refs/heads")
         .run_for_output()?;
 
     if result.status.success() {
-        Ok(output_lines(result))
+        output_lines(result)
     } else {
         Err(anyhow!("Failed to get branches"))
     }
 }
 
+// each local branch with the unix time of its last commit and that time relative to now,
+// e.g. "3 months ago"
+pub fn get_branch_dates() -> Result<Vec<(String, u64, String)>> {
+    let result = git_read_only()
+        .arg("for-each-ref")
+        .arg("--format")
+        .arg("%(refname:lstrip=2) %(committerdate:unix) %(committerdate:relative)")
+        .arg("refs/heads")
+        .run_for_output()?;
+
+    if result.status.success() {
+        // $ git for-each-ref --format '%(refname:lstrip=2) %(committerdate:unix) %(committerdate:relative)'
+        // main 1714000000 2 days ago
+        // scratch 1705000000 3 months ago
+        Ok(output_lines(result)?
+            .iter()
+            .filter_map(|line| {
+                let mut parts = line.splitn(3, ' ');
+                let branch = parts.next()?;
+                let date = parts.next()?.parse().ok()?;
+                let relative = parts.next()?;
+                Some((branch.to_string(), date, relative.to_string()))
+            })
+            .collect())
+    } else {
+        Err(anyhow!("Failed to get branch dates"))
+    }
+}
+
+// the operations git-up needs from git, so they can be served by something other than the
+// `git` binary. Subprocess is the only backend for now; a libgit2 one would implement this
+// behind a `git2` Cargo feature and be picked in backend(). the free functions in this module
+// go through backend(), so callers don't know which one is active
+pub trait Backend: Sync {
+    fn resolve(&self, name: &str) -> Option<String>;
+    fn is_ancestor(&self, a: &str, b: &str) -> bool;
+    // counts the commits only on `local` and only on `remote`
+    fn ahead_behind(&self, local: &str, remote: &str) -> Result<(usize, usize)>;
+    fn create_branch(&self, local_branch: &str, sha: &str) -> Result<()>;
+    // without `force`, refuses to delete a branch that isn't merged
+    fn delete_branch(&self, local_branch: &str, force: bool) -> Result<()>;
+    fn update_ref(&self, full_branch: &str, remote_branch: &str) -> Result<()>;
+    // merges into whatever is checked out in `worktree_path`, or in the current worktree
+    fn fast_forward_merge(&self, branch: &str, worktree_path: Option<&str>) -> Result<()>;
+}
+
+pub fn backend() -> &'static dyn Backend {
+    &Subprocess
+}
+
+// shells out to the `git` on PATH for every operation
+pub struct Subprocess;
+
+impl Backend for Subprocess {
+    fn resolve(&self, name: &str) -> Option<String> {
+        let result = git_read_only()
+            .arg("rev-parse")
+            .arg("--verify")
+            .arg("--quiet")
+            .arg(name)
+            .run_for_output();
+
+        match result {
+            Ok(output) => {
+                if output.status.success() {
+                    output_string(output)
+                        .ok()
+                        .map(|stdout| stdout.trim().to_string())
+                } else {
+                    None
+                }
+            }
+            Err(_) => None,
+        }
+    }
+
+    fn is_ancestor(&self, a: &str, b: &str) -> bool {
+        let result = git_read_only()
+            .arg("merge-base")
+            .arg("--is-ancestor")
+            .arg(a)
+            .arg(b)
+            .run_for_output();
+
+        match result {
+            Ok(output) => output.status.success(),
+            Err(_) => false,
+        }
+    }
+
+    fn ahead_behind(&self, local: &str, remote: &str) -> Result<(usize, usize)> {
+        let result = git_read_only()
+            .arg("rev-list")
+            .arg("--left-right")
+            .arg("--count")
+            .arg(format!("{}...{}", local, remote))
+            .run_for_output()?;
+
+        if result.status.success() {
+            // $ git rev-list --left-right --count main...origin/main
+            // 2	3
+            let counts = output_string(result)?;
+            let mut counts = counts.split_whitespace().map(str::parse::<usize>);
+            match (counts.next(), counts.next()) {
+                (Some(Ok(ahead)), Some(Ok(behind))) => Ok((ahead, behind)),
+                _ => Err(anyhow!("Can't parse ahead/behind counts")),
+            }
+        } else {
+            Err(anyhow!(
+                "Failed to count commits between {} and {}",
+                local,
+                remote
+            ))
+        }
+    }
+
+    fn create_branch(&self, local_branch: &str, sha: &str) -> Result<()> {
+        let result = git()
+            .arg("branch")
+            .arg(local_branch)
+            .arg(sha)
+            .run_for_output()?;
+
+        if result.status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("Failed to create branch {}", local_branch))
+        }
+    }
+
+    // without `force`, git refuses to delete a branch that isn't merged into its upstream or,
+    // when that's gone, into HEAD
+    fn delete_branch(&self, local_branch: &str, force: bool) -> Result<()> {
+        let result = git()
+            .arg("branch")
+            .arg(if force { "-D" } else { "-d" })
+            .arg("--quiet")
+            .arg(local_branch)
+            .run_for_output()?;
+
+        if result.status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("Failed to delete branch"))
+        }
+    }
+
+    // the reflog message names git-up, so `git reflog <branch>` shows why the branch moved
+    fn update_ref(&self, full_branch: &str, remote_branch: &str) -> Result<()> {
+        let result = git()
+            .arg("update-ref")
+            .arg("-m")
+            .arg(format!(
+                "git-up: fast-forward to {}",
+                remote_branch
+                    .strip_prefix("refs/remotes/")
+                    .unwrap_or(remote_branch)
+            ))
+            .arg(full_branch)
+            .arg(remote_branch)
+            .run_for_output()?;
+
+        if result.status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("Failed to update ref"))
+        }
+    }
+
+    fn fast_forward_merge(&self, branch: &str, worktree_path: Option<&str>) -> Result<()> {
+        let mut command = git();
+        if let Some(worktree_path) = worktree_path {
+            command.arg("-C").arg(worktree_path);
+        }
+        let result = command
+            .arg("merge")
+            .arg("--ff-only")
+            .arg("--quiet")
+            .arg(branch)
+            .run_for_output()?;
+
+        if result.status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("Failed to fast forward merge ref"))
+        }
+    }
+}
+
+// every command that talks to a remote may need credentials: an SSH passphrase, a host key
+// confirmation, or a credential helper asking for a username and password. those commands
+// leave stdin and stderr connected to the terminal so the prompts reach the user:
+//
+//   fetch, pull, push, ls-remote (check_reachable, advertised_head, get_remote_tags), remote
+//   set-head, and submodule update
+//
+// `run` and `run_with_timeout` inherit all three streams, and `run_with_prompts` captures only
+// stdout. `run_for_output` captures stdout and stderr and closes stdin, so it is only for
+// commands that stay local
 trait Runnable {
     fn run_for_output(&mut self) -> Result<Output>;
+    fn run_with_prompts(&mut self) -> Result<Output>;
     fn run(&mut self) -> Result<()>;
+    fn run_with_timeout(&mut self, timeout: Duration) -> Result<()>;
 }
 
 impl Runnable for Command {
     fn run_for_output(&mut self) -> Result<Output> {
-        debug!("Running command: {:?}", self);
-        self.output()
-            .tap_ok(|output| {
-                debug!("Command result: {:?}", output);
-            })
-            .with_context(|| "Failed to execute command")
+        self.stdin(Stdio::null()).stderr(Stdio::piped());
+        capture_stdout(self)
+    }
+
+    fn run_with_prompts(&mut self) -> Result<Output> {
+        self.stdin(Stdio::inherit()).stderr(Stdio::inherit());
+        capture_stdout(self)
     }
 
     fn run(&mut self) -> Result<()> {
         debug!("Running command: {:?}", self);
-        let result = self
-            .spawn()?
-            .wait()
-            .with_context(|| "Failed to execute command")?;
+        let mut child = self.spawn()?;
+        let _tracked = interrupt::track(child.id());
+        let result = child.wait().with_context(|| "Failed to execute command")?;
 
         if result.success() {
             Ok(())
@@ -286,4 +1299,129 @@ impl Runnable for Command {
             Err(anyhow!("Failed to execute command"))
         }
     }
+
+    // like `run`, but kills the command once `timeout` has passed
+    fn run_with_timeout(&mut self, timeout: Duration) -> Result<()> {
+        debug!("Running command with a {:?} timeout: {:?}", timeout, self);
+        let mut child = self.spawn()?;
+        let _tracked = interrupt::track(child.id());
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(result) = child
+                .try_wait()
+                .with_context(|| "Failed to execute command")?
+            {
+                return if result.success() {
+                    Ok(())
+                } else {
+                    Err(anyhow!("Failed to execute command"))
+                };
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(anyhow!("Timed out after {} seconds", timeout.as_secs()));
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+// like Command::output, but the child is tracked while it runs so Ctrl-C stops it
+fn capture_stdout(command: &mut Command) -> Result<Output> {
+    debug!("Running command: {:?}", command);
+    command
+        .stdout(Stdio::piped())
+        .spawn()
+        .and_then(|child| {
+            let _tracked = interrupt::track(child.id());
+            child.wait_with_output()
+        })
+        .tap_ok(|output| {
+            debug!("Command result: {:?}", output);
+        })
+        .with_context(|| "Failed to execute command")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn substitutes_the_branch_inside_and_outside_quotes() {
+        assert_eq!(substitute_branch("echo {branch}"), "echo \"$1\"");
+        assert_eq!(substitute_branch("echo \"x {branch}\""), "echo \"x $1\"");
+        assert_eq!(substitute_branch("echo '{branch}'"), "echo ''\"$1\"''");
+        assert_eq!(substitute_branch("echo \\'{branch}"), "echo \\'\"$1\"");
+    }
+
+    #[test]
+    fn never_starts_a_pager() {
+        assert!(git()
+            .get_envs()
+            .any(|(key, value)| key == "GIT_PAGER" && value == Some("cat".as_ref())));
+    }
+
+    #[test]
+    fn only_read_only_commands_skip_optional_locks() {
+        let skips_locks = |command: Command| {
+            command
+                .get_envs()
+                .any(|(key, value)| key == "GIT_OPTIONAL_LOCKS" && value == Some("0".as_ref()))
+        };
+        assert!(skips_locks(git_read_only()));
+        assert!(!skips_locks(git()));
+    }
+
+    #[test]
+    fn prefers_origin_as_main_remote() {
+        let remotes = lines(&[
+            "fork\tgit@example.com:me/repo.git (fetch)",
+            "fork\tgit@example.com:me/repo.git (push)",
+            "origin\tgit@example.com:org/repo.git (fetch)",
+            "origin\tgit@example.com:org/repo.git (push)",
+        ]);
+        assert_eq!(parse_main_remote(&remotes).as_deref(), Some("origin"));
+    }
+
+    #[test]
+    fn skips_remotes_without_a_fetch_url() {
+        assert_eq!(parse_main_remote(&lines(&["fork\t (push)"])), None);
+        assert_eq!(
+            parse_main_remote(&lines(&["fork\tgit@example.com:me/repo.git (fetch)"])).as_deref(),
+            Some("fork")
+        );
+    }
+
+    #[test]
+    fn kills_commands_that_time_out() {
+        let started = Instant::now();
+        let result = Command::new("sleep")
+            .arg("5")
+            .run_with_timeout(Duration::from_millis(100));
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn parses_branch_remotes() {
+        let branch_remotes = parse_branch_remotes(&lines(&[
+            "branch.main.remote origin",
+            "branch.release.1.0.remote upstream",
+            "branch.feature.remote.remote  origin ",
+            "branch.spaced.remote my remote",
+        ]));
+
+        assert_eq!(branch_remotes["main"], "origin");
+        assert_eq!(branch_remotes["release.1.0"], "upstream");
+        assert_eq!(branch_remotes["feature.remote"], "origin");
+        assert_eq!(branch_remotes["spaced"], "my remote");
+        assert_eq!(branch_remotes.len(), 4);
+    }
 }
@@ -0,0 +1,98 @@
+use std::{
+    io::{self, IsTerminal},
+    os::raw::c_int,
+    sync::atomic::{AtomicBool, AtomicI32, Ordering},
+};
+
+#[cfg(unix)]
+const SIGINT: c_int = 2;
+#[cfg(unix)]
+const SIGTERM: c_int = 15;
+
+// exit status for a run stopped by Ctrl-C, 128 + SIGINT like a shell reports it
+pub const INTERRUPTED: c_int = 130;
+
+// the handler may only do async-signal-safe work, so running children are kept in fixed slots
+// of atomics rather than behind a lock. a child that finds every slot taken isn't tracked; it
+// still gets the terminal's SIGINT along with the rest of the process group
+const SLOTS: usize = 64;
+static CHILDREN: [AtomicI32; SLOTS] = [const { AtomicI32::new(0) }; SLOTS];
+
+// whether stdout was a terminal when the handler was installed, so colors can be reset on it
+static STDOUT_IS_TERMINAL: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    fn kill(pid: c_int, sig: c_int) -> c_int;
+    fn write(fd: c_int, buf: *const u8, count: usize) -> isize;
+    fn _exit(status: c_int) -> !;
+}
+
+// on Ctrl-C, stops every running git command, resets the terminal's colors and exits with 130.
+// git commands get SIGTERM rather than SIGKILL so they can remove their lock files. on other
+// platforms Ctrl-C keeps its default behavior
+pub fn install() {
+    STDOUT_IS_TERMINAL.store(io::stdout().is_terminal(), Ordering::SeqCst);
+    #[cfg(unix)]
+    unsafe {
+        signal(SIGINT, on_interrupt);
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: c_int) {
+    for child in &CHILDREN {
+        let pid = child.load(Ordering::SeqCst);
+        if pid > 0 {
+            unsafe {
+                kill(pid, SIGTERM);
+            }
+        }
+    }
+    colored::control::unset_override();
+    if STDOUT_IS_TERMINAL.load(Ordering::SeqCst) {
+        // a colored line cut off halfway would leave the prompt colored
+        let reset = b"\x1b[0m";
+        unsafe {
+            write(1, reset.as_ptr(), reset.len());
+        }
+    }
+    unsafe { _exit(INTERRUPTED) }
+}
+
+// a running child, killed by the handler until this is dropped
+pub(crate) struct Tracked(Option<usize>);
+
+pub(crate) fn track(pid: u32) -> Tracked {
+    let Ok(pid) = c_int::try_from(pid) else {
+        return Tracked(None);
+    };
+    Tracked(CHILDREN.iter().position(|child| {
+        child
+            .compare_exchange(0, pid, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }))
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0 {
+            CHILDREN[slot].store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frees_the_slot_once_the_child_is_done() {
+        let tracked = track(4_000_000);
+        let slot = tracked.0.unwrap();
+        assert_eq!(CHILDREN[slot].load(Ordering::SeqCst), 4_000_000);
+        drop(tracked);
+        assert_eq!(CHILDREN[slot].load(Ordering::SeqCst), 0);
+    }
+}
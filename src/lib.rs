@@ -0,0 +1,1057 @@
+mod config;
+pub mod git;
+pub mod interrupt;
+mod pattern;
+mod prompt;
+pub mod report;
+mod state;
+mod sync;
+
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use config::FileConfig;
+use log::{debug, info, warn};
+use report::{Action, BranchOutcome, BranchReport, SkipReason};
+use state::State;
+
+pub use sync::{
+    execute_step, has_diverged, plan_branch, process_branch, BranchStatus, OnConflict,
+    PlannedBranch, Step, SyncContext,
+};
+
+// options for a single run; the binary builds this from the command line, and settings from
+// .git-up.toml and git config are layered on top of it inside `run`
+#[derive(Clone)]
+pub struct Config {
+    pub directory: Option<PathBuf>,
+    pub remote: Option<String>,
+    pub default_branch: Option<String>,
+    pub dry_run: bool,
+    // only delete branches, leaving every other branch as it is
+    pub delete_only: bool,
+    pub no_fetch: bool,
+    pub all_remotes: bool,
+    pub prune_tags: bool,
+    pub rebase: bool,
+    pub pull: bool,
+    pub autostash: bool,
+    pub detect_squash: bool,
+    pub rebase_gone: bool,
+    pub prune_merged: bool,
+    pub local_only: bool,
+    pub archive: bool,
+    // branches are pushed here before being deleted, and kept if the push fails
+    pub backup_remote: Option<String>,
+    pub force: bool,
+    // goes past --max-deletes and --max-updates without asking
+    pub allow_over_cap: bool,
+    pub keep_current: bool,
+    pub on_conflict: OnConflict,
+    pub confirm: bool,
+    // like `confirm`, but without a terminal to ask at, every branch is kept instead of deleted
+    pub confirm_deletes: bool,
+    pub update_submodules: bool,
+    pub set_head: bool,
+    pub digest: bool,
+    pub fail_fast: bool,
+    pub plan: bool,
+    pub only_current: bool,
+    pub interactive: bool,
+    pub resume: bool,
+    pub fetch_timeout: Option<Duration>,
+    pub fetch_retries: usize,
+    pub fetch_args: Vec<String>,
+    pub since: Option<Duration>,
+    // after syncing, list branches with an upstream but no commits in this long
+    pub stale: Option<Duration>,
+    pub jobs: usize,
+    pub max_deletes: Option<usize>,
+    pub max_updates: Option<usize>,
+    pub branches: Vec<String>,
+    pub exclude: Vec<String>,
+    // only branches tracking this remote are synced; untracked ones count as the main remote's
+    pub remote_filter: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            remote: None,
+            default_branch: None,
+            dry_run: false,
+            delete_only: false,
+            no_fetch: false,
+            all_remotes: false,
+            prune_tags: false,
+            rebase: false,
+            pull: false,
+            autostash: false,
+            detect_squash: false,
+            rebase_gone: false,
+            prune_merged: false,
+            local_only: false,
+            archive: false,
+            backup_remote: None,
+            force: false,
+            allow_over_cap: false,
+            keep_current: false,
+            on_conflict: OnConflict::Warn,
+            confirm: false,
+            confirm_deletes: false,
+            update_submodules: false,
+            set_head: false,
+            digest: false,
+            fail_fast: false,
+            plan: false,
+            only_current: false,
+            interactive: false,
+            resume: false,
+            fetch_timeout: None,
+            fetch_retries: 2,
+            fetch_args: Vec::new(),
+            since: None,
+            stale: None,
+            jobs: 1,
+            max_deletes: None,
+            max_updates: None,
+            branches: Vec::new(),
+            exclude: Vec::new(),
+            remote_filter: None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Summary {
+    pub branches: Vec<BranchReport>,
+    pub timings: Timings,
+}
+
+// how long the main phases of a run took. phases that didn't run are zero
+#[derive(Default)]
+pub struct Timings {
+    pub fetch: Duration,
+    // planning and syncing every branch, including any time spent at prompts
+    pub branches: Duration,
+}
+
+impl Summary {
+    pub fn failures(&self) -> usize {
+        self.branches
+            .iter()
+            .filter(|report| is_failure(report))
+            .count()
+    }
+
+    // protected branches that would otherwise have been deleted
+    pub fn spared(&self) -> usize {
+        self.branches
+            .iter()
+            .filter(|report| {
+                matches!(
+                    report.outcome,
+                    BranchOutcome::Skipped {
+                        reason: SkipReason::Protected,
+                        ..
+                    }
+                )
+            })
+            .count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.branches
+            .iter()
+            .filter(|report| matches!(report.outcome.action(), Action::Warning))
+            .count()
+    }
+}
+
+// reported while a run is in progress so callers can show output as it happens
+pub enum Event<'a> {
+    NoRemotes,
+    NoFetchUrl,
+    NotOnBranch,
+    // HEAD is unborn, e.g. in a repository that was just created
+    NoCommits,
+    RemoteHeadSet {
+        remote: &'a str,
+        branch: &'a str,
+    },
+    TagDeleted {
+        tag: &'a str,
+        sha: &'a str,
+    },
+    // about to process the `current`th of `total` branches, counting from 1. only sent when
+    // branches are processed one at a time
+    Processing {
+        branch: &'a str,
+        current: usize,
+        total: usize,
+    },
+    // commits on the default branch's upstream that the local default branch doesn't have yet
+    Digest {
+        upstream: &'a str,
+        commits: &'a [String],
+    },
+    Plan(&'a [PlannedBranch]),
+    Branch(&'a BranchReport),
+    // sent once after every branch is synced, and only when some are stale
+    Stale(&'a [StaleBranch]),
+    SubmodulesUpdated(&'a Result<()>),
+}
+
+// a branch whose upstream still exists but hasn't seen a commit in a while
+pub struct StaleBranch {
+    pub branch: String,
+    // when the last commit was made, relative to now, e.g. "3 months ago"
+    pub last_commit: String,
+}
+
+// one deleted branch per line, with its name and the sha it pointed at
+pub fn undo_record(reports: &[BranchReport]) -> String {
+    reports
+        .iter()
+        .filter_map(|report| match &report.outcome {
+            BranchOutcome::Deleted { old } => Some(format!("{} {}\n", report.branch, old)),
+            _ => None,
+        })
+        .collect()
+}
+
+// recreates the branches in a record written by `undo_record`, calling `on_restored` with the
+// name and sha of each one. branches that exist again are left alone
+pub fn undo(
+    directory: Option<&Path>,
+    record: &str,
+    mut on_restored: impl FnMut(&str, &str),
+) -> Result<()> {
+    git::set_work_dir(directory);
+    for (index, line) in record.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (branch, sha) = line
+            .split_once(' ')
+            .ok_or(anyhow!("line {}: expected `<branch> <sha>`", index + 1))?;
+        if git::has_ref(&format!("refs/heads/{}", branch)) {
+            warn!("Branch {} already exists; leaving it alone", branch);
+            continue;
+        }
+        git::create_branch(branch, sha)?;
+        on_restored(branch, sha);
+    }
+    Ok(())
+}
+
+pub fn run(config: Config) -> Result<Summary> {
+    run_with(config, |_| {})
+}
+
+pub fn run_with(config: Config, mut on_event: impl FnMut(Event)) -> Result<Summary> {
+    if let Some(directory) = &config.directory {
+        if !directory.is_dir() {
+            return Err(anyhow!(
+                "Cannot run in {}: not a directory",
+                directory.display()
+            ));
+        }
+    }
+    git::set_work_dir(config.directory.as_deref());
+
+    // checking out and merging branches need a working tree, so bare repositories and
+    // running from inside .git are refused up front
+    if !git::is_inside_work_tree() {
+        return Err(anyhow!("git-up must be run inside a git working tree"));
+    }
+
+    // fast-forwarding or checking out branches mid-operation could lose the user's work
+    if let Some(operation) = git::in_progress_operation() {
+        return Err(anyhow!(
+            "A {} is in progress; finish or abort it before running git-up",
+            operation
+        ));
+    }
+
+    // every branch would be compared against commits that don't exist yet
+    if !git::has_ref("HEAD") {
+        on_event(Event::NoCommits);
+        return Ok(Summary::default());
+    }
+
+    let remotes = git::get_remotes().with_context(|| "Failed to get remotes")?;
+    if remotes.is_empty() && !config.local_only {
+        on_event(Event::NoRemotes);
+        return Ok(Summary::default());
+    }
+
+    let file_config = FileConfig::load()?;
+
+    let mut timings = Timings::default();
+
+    // with --local-only nothing is fetched, and branches are compared against the local
+    // default branch instead of their upstreams
+    let (remote, synced_remotes, default_branch, full_default_branch) = if config.local_only {
+        let default_branch = match config.default_branch {
+            Some(default_branch) => {
+                if !git::has_ref(&format!("refs/heads/{}", default_branch)) {
+                    return Err(anyhow!("Branch {} does not exist", default_branch));
+                }
+                default_branch
+            }
+            None => git::get_local_default_branch()?,
+        };
+        let full_default_branch = format!("refs/heads/{}", default_branch);
+        (
+            String::new(),
+            Vec::new(),
+            default_branch,
+            full_default_branch,
+        )
+    } else {
+        let remote = match config.remote.or(file_config.remote) {
+            Some(remote) => {
+                if !remotes.contains(&remote) {
+                    return Err(anyhow!("Remote {} does not exist", remote));
+                }
+                remote
+            }
+            None => match git::get_main_remote()? {
+                Some(remote) => remote,
+                None => {
+                    on_event(Event::NoFetchUrl);
+                    return Ok(Summary::default());
+                }
+            },
+        };
+        info!(
+            "Syncing against {} ({})",
+            remote,
+            git::get_remote_url(&remote).unwrap_or_else(|| "no URL".to_string())
+        );
+
+        let all_remotes = config.all_remotes
+            || git::get_config(&["--type=bool", "--get", "git-up.fetchAll"])
+                .with_context(|| "Failed to execute git config command")?
+                .first()
+                .is_some_and(|value| value == "true");
+        let synced_remotes = if all_remotes {
+            remotes.clone()
+        } else {
+            vec![remote.clone()]
+        };
+
+        let mut fetch_args: Vec<String> = git::get_config(&["--get-all", "git-up.fetchArgs"])
+            .with_context(|| "Failed to execute git config command")?
+            .iter()
+            .flat_map(|args| args.split_whitespace().map(String::from))
+            .collect();
+        fetch_args.extend(config.fetch_args);
+        // the remote is added by git-up, and naming another one would fetch it instead
+        if let Some(arg) = fetch_args.iter().find(|arg| remotes.contains(arg)) {
+            return Err(anyhow!(
+                "Fetch argument {} is a remote; git-up adds the remote to fetch itself",
+                arg
+            ));
+        }
+
+        let fetch_started = Instant::now();
+        let mut no_fetch = config.no_fetch;
+        for synced_remote in &synced_remotes {
+            if no_fetch {
+                debug!("Skipping fetch of {}", synced_remote);
+            } else if let Err(e) = fetch(
+                synced_remote,
+                config.fetch_timeout,
+                config.fetch_retries,
+                &fetch_args,
+            ) {
+                // the remote-tracking branches from the last successful fetch are still usable
+                let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+                if !interactive
+                    || !prompt::confirm(&format!(
+                        "Fetching {} failed ({:#}). Continue without fetching?",
+                        synced_remote, e
+                    ))?
+                {
+                    return Err(e);
+                }
+                no_fetch = true;
+            }
+        }
+
+        timings.fetch = fetch_started.elapsed();
+
+        let remote_head = format!("refs/remotes/{}/HEAD", remote);
+        if config.set_head && git::symbolic_ref(&remote_head, false).is_none() {
+            if config.dry_run {
+                debug!("Skipping set-head of {} in dry-run mode", remote);
+            } else {
+                git::set_remote_head(&remote).with_context(|| "Failed to set remote HEAD")?;
+                let branch = git::get_default_branch(&remote, false)?;
+                on_event(Event::RemoteHeadSet {
+                    remote: &remote,
+                    branch: &branch,
+                });
+            }
+        }
+
+        // guessing the default branch looks at remote-tracking branches, so it waits for the fetch
+        let default_branch = match config.default_branch {
+            Some(default_branch) => {
+                if !git::has_ref(&format!("refs/remotes/{}/{}", remote, default_branch)) {
+                    return Err(anyhow!(
+                        "Default branch {} does not exist on {}",
+                        default_branch,
+                        remote
+                    ));
+                }
+                default_branch
+            }
+            // asking the remote needs the network, which --no-fetch is meant to avoid
+            None => git::get_default_branch(&remote, !no_fetch)?,
+        };
+        let full_default_branch = format!("refs/remotes/{}/{}", remote, default_branch);
+
+        let full_local_default = format!("refs/heads/{}", default_branch);
+        if config.digest && git::has_ref(&full_local_default) {
+            let commits = git::make_range(&full_local_default, &full_default_branch)
+                .and_then(|range| range.commits())
+                .with_context(|| "Failed to list new commits")?;
+            if !commits.is_empty() {
+                on_event(Event::Digest {
+                    upstream: &format!("{}/{}", remote, default_branch),
+                    commits: &commits,
+                });
+            }
+        }
+
+        if config.prune_tags {
+            prune_tags(&remote, config.dry_run, &mut on_event)
+                .with_context(|| "Failed to prune tags")?;
+        }
+
+        (remote, synced_remotes, default_branch, full_default_branch)
+    };
+
+    let branches_to_remotes =
+        git::get_branch_remotes().with_context(|| "Failed to execute git config command")?;
+    debug!("Map of branches to remotes: {:?}", branches_to_remotes);
+
+    let mut protected_branches = git::get_config(&["--get-all", "git-up.protected"])
+        .with_context(|| "Failed to execute git config command")?;
+    protected_branches.extend(file_config.protected);
+    protected_branches.push(default_branch.clone());
+    debug!("Protected branch patterns: {:?}", protected_branches);
+
+    let autostash = config.autostash
+        || match file_config.autostash {
+            Some(autostash) => autostash,
+            None => git::get_config(&["--type=bool", "--get", "git-up.autostash"])
+                .with_context(|| "Failed to execute git config command")?
+                .first()
+                .is_some_and(|value| value == "true"),
+        };
+    let rebase = config.rebase || file_config.rebase.unwrap_or(false);
+
+    if let Some(backup_remote) = &config.backup_remote {
+        if !remotes.contains(backup_remote) {
+            return Err(anyhow!("Remote {} does not exist", backup_remote));
+        }
+    }
+
+    let pre_delete = git::get_config(&["--get", "git-up.preDelete"])
+        .with_context(|| "Failed to execute git config command")?
+        .into_iter()
+        .next();
+
+    let worktree_branches =
+        git::get_worktree_branches().with_context(|| "Failed to list worktrees")?;
+    debug!("Map of branches to worktrees: {:?}", worktree_branches);
+
+    let refs = git::get_refs().with_context(|| "Failed to list refs")?;
+
+    let local_branches = if config.only_current {
+        match git::symbolic_ref("HEAD", true) {
+            Some(current_branch) => vec![current_branch],
+            None => {
+                on_event(Event::NotOnBranch);
+                return Ok(Summary::default());
+            }
+        }
+    } else if config.branches.is_empty() {
+        git::get_branches().with_context(|| "Failed to get local branches")?
+    } else {
+        for branch in &config.branches {
+            if !git::has_ref(&format!("refs/heads/{}", branch)) {
+                return Err(anyhow!("Branch {} does not exist", branch));
+            }
+        }
+        // naming a branch twice shouldn't process it twice
+        let mut branches: Vec<String> = Vec::with_capacity(config.branches.len());
+        for branch in &config.branches {
+            if !branches.contains(branch) {
+                branches.push(branch.clone());
+            }
+        }
+        branches
+    };
+    let local_branches: Vec<String> = local_branches
+        .into_iter()
+        .filter(|local_branch| {
+            let excluded = pattern::matches_any(&config.exclude, local_branch);
+            if excluded {
+                debug!("Excluding branch {}", local_branch);
+            }
+            !excluded
+        })
+        .collect();
+    let local_branches: Vec<String> = match &config.remote_filter {
+        Some(remote_filter) => {
+            if !remotes.contains(remote_filter) {
+                return Err(anyhow!("Remote {} does not exist", remote_filter));
+            }
+            local_branches
+                .into_iter()
+                .filter(|local_branch| {
+                    let tracked = branches_to_remotes.get(local_branch).unwrap_or(&remote);
+                    if tracked != remote_filter {
+                        debug!("Skipping {}; it tracks {}", local_branch, tracked);
+                    }
+                    tracked == remote_filter
+                })
+                .collect()
+        }
+        None => local_branches,
+    };
+
+    // progress is only kept for runs that may need resuming
+    let resume = config.resume
+        || git::get_config(&["--type=bool", "--get", "git-up.resume"])
+            .with_context(|| "Failed to execute git config command")?
+            .first()
+            .is_some_and(|value| value == "true");
+    let state = State::open(resume, config.dry_run)?;
+    let handled_branches = state.handled_branches();
+    let local_branches: Vec<String> = local_branches
+        .into_iter()
+        .filter(|local_branch| {
+            let handled = handled_branches.contains(local_branch);
+            if handled {
+                debug!(
+                    "Skipping {}; already handled by the interrupted run",
+                    local_branch
+                );
+            }
+            !handled
+        })
+        .collect();
+
+    let since = config.since.map(cutoff);
+
+    let make_context = |local_branch: &str, current_branch: &Option<String>| SyncContext {
+        remote: remote.clone(),
+        default_branch: default_branch.clone(),
+        full_default_branch: full_default_branch.clone(),
+        local_branch: local_branch.to_string(),
+        current_branch: current_branch.clone(),
+        branches_to_remotes: branches_to_remotes.clone(),
+        synced_remotes: synced_remotes.clone(),
+        remotes: remotes.clone(),
+        protected_branches: protected_branches.clone(),
+        worktree_branches: worktree_branches.clone(),
+        refs: refs.clone(),
+        rebase,
+        pull: config.pull,
+        autostash,
+        detect_squash: config.detect_squash,
+        rebase_gone: config.rebase_gone,
+        prune_merged: config.prune_merged,
+        local_only: config.local_only,
+        archive: config.archive,
+        backup_remote: config.backup_remote.clone(),
+        force: config.force,
+        keep_current: config.keep_current,
+        delete_only: config.delete_only,
+        on_conflict: config.on_conflict,
+        pre_delete: pre_delete.clone(),
+        since,
+        // prompting without a terminal would hang, e.g. in CI
+        confirm: (config.confirm || config.confirm_deletes) && io::stdout().is_terminal(),
+        keep_deletes: config.confirm_deletes && !io::stdout().is_terminal(),
+        dry_run: config.dry_run,
+    };
+
+    // divergence can only be judged against freshly fetched upstreams, so by now the fetch,
+    // --prune-tags and --set-head have already run. no branch has been touched yet
+    if let OnConflict::Abort = config.on_conflict {
+        if let Some(current_branch) = git::symbolic_ref("HEAD", true)
+            .filter(|current_branch| local_branches.contains(current_branch))
+        {
+            let context = make_context(&current_branch, &Some(current_branch.clone()));
+            if has_diverged(&context)? {
+                return Err(anyhow!(
+                    "{} has diverged from its upstream; stopping before changing any branch",
+                    current_branch
+                ));
+            }
+        }
+    }
+
+    // the picker needs someone at a terminal to answer it; otherwise this is a normal run
+    let interactive = config.interactive && io::stdin().is_terminal() && io::stderr().is_terminal();
+
+    let branches_started = Instant::now();
+
+    // with --plan every branch is decided before anything is written, and the decisions are
+    // carried over to execution instead of being made again
+    let capped = config.max_deletes.is_some() || config.max_updates.is_some();
+    let plan = if config.plan || interactive || capped {
+        let current_branch = git::symbolic_ref("HEAD", true);
+        let plan: Vec<PlannedBranch> = local_branches
+            .iter()
+            .map(|local_branch| PlannedBranch {
+                branch: local_branch.clone(),
+                step: plan_branch(&make_context(local_branch, &current_branch))
+                    .unwrap_or_else(|e| Step::Done(BranchOutcome::Failed(e.to_string()))),
+            })
+            .collect();
+        // the caps apply to what the user picked, not to everything that could change
+        let plan = if interactive {
+            pick_steps(plan, &default_branch)?
+        } else {
+            plan
+        };
+        let exceeded = exceeded_cap(&plan, config.max_deletes, config.max_updates);
+        if config.plan {
+            on_event(Event::Plan(&plan));
+            let nothing_to_do = plan
+                .iter()
+                .all(|planned| matches!(planned.step, Step::Done(_)));
+            let question = match &exceeded {
+                Some(exceeded) => format!("{}. Proceed?", exceeded),
+                None => "Proceed?".to_string(),
+            };
+            if config.dry_run {
+                if let Some(exceeded) = &exceeded {
+                    warn!("{}", exceeded);
+                }
+            } else if !nothing_to_do && !prompt::confirm(&question)? {
+                info!("Plan not confirmed; nothing was changed");
+                return Ok(Summary::default());
+            }
+        } else if let Some(exceeded) = exceeded {
+            // once over a cap, it takes --allow-over-cap or a yes at the terminal to go ahead
+            if config.dry_run || config.allow_over_cap {
+                warn!("{}", exceeded);
+            } else if io::stdin().is_terminal() && io::stderr().is_terminal() {
+                on_event(Event::Plan(&plan));
+                if !prompt::confirm(&format!("{}. Proceed?", exceeded))? {
+                    info!("Plan not confirmed; nothing was changed");
+                    return Ok(Summary::default());
+                }
+            } else {
+                return Err(anyhow!("{}; pass --allow-over-cap to go ahead", exceeded));
+            }
+        }
+        Some(plan)
+    } else {
+        None
+    };
+
+    // one entry per branch, in the same order. without a plan each branch is planned and
+    // executed together
+    let mut steps: Vec<Option<Step>> = match plan {
+        Some(plan) => plan.into_iter().map(|planned| Some(planned.step)).collect(),
+        None => local_branches.iter().map(|_| None).collect(),
+    };
+
+    let mut results: Vec<BranchReport> = Vec::new();
+    if config.jobs > 1 {
+        // detached HEAD leaves no current branch
+        let mut current_branch = git::symbolic_ref("HEAD", true);
+
+        // the current branch may be fast-forwarded or checked out away from, so it is
+        // handled on its own before any of the other branches run concurrently
+        let mut handled_branch = None;
+        if let Some(branch) = current_branch.clone() {
+            if let Some(index) = local_branches.iter().position(|b| *b == branch) {
+                let report =
+                    sync_branch(&make_context(&branch, &current_branch), steps[index].take());
+                state.record(&report);
+                results.push(report);
+                handled_branch = Some(branch);
+                current_branch = git::symbolic_ref("HEAD", true);
+            }
+        }
+
+        if !(config.fail_fast && results.iter().any(is_failure)) {
+            let work: Vec<(SyncContext, Option<Step>)> = local_branches
+                .iter()
+                .zip(steps)
+                .filter(|(local_branch, _)| handled_branch.as_ref() != Some(*local_branch))
+                .map(|(local_branch, step)| (make_context(local_branch, &current_branch), step))
+                .collect();
+            results.extend(sync_branches(work, config.jobs, config.fail_fast, &state));
+        }
+        results.sort_by_key(|report| {
+            local_branches
+                .iter()
+                .position(|local_branch| *local_branch == report.branch)
+        });
+
+        for report in &results {
+            on_event(Event::Branch(report));
+        }
+    } else {
+        let mut current_branch = git::symbolic_ref("HEAD", true);
+        let mut steps = steps.into_iter();
+        for (index, local_branch) in local_branches.iter().enumerate() {
+            on_event(Event::Processing {
+                branch: local_branch,
+                current: index + 1,
+                total: local_branches.len(),
+            });
+            let step = steps.next().flatten();
+            let report = sync_branch(&make_context(local_branch, &current_branch), step);
+            state.record(&report);
+            on_event(Event::Branch(&report));
+            // deleting the current branch checks out the default branch first
+            if matches!(report.outcome, BranchOutcome::Deleted { .. })
+                && current_branch.as_ref() == Some(local_branch)
+            {
+                current_branch = git::symbolic_ref("HEAD", true);
+            }
+            let failed = is_failure(&report);
+            results.push(report);
+            if config.fail_fast && failed {
+                debug!("Stopping after the first failed branch");
+                break;
+            }
+        }
+    }
+
+    timings.branches = branches_started.elapsed();
+
+    // with --local-only there are no upstreams, so nothing counts as stale
+    if let Some(stale) = config.stale.filter(|_| !config.local_only) {
+        let cutoff = cutoff(stale);
+        // dates are read again since the branches may have moved during the run
+        let stale_branches: Vec<StaleBranch> = git::get_branch_dates()
+            .with_context(|| "Failed to list branch dates")?
+            .into_iter()
+            .filter(|(branch, date, _)| {
+                *date < cutoff
+                    && results.iter().any(|report| {
+                        report.branch == *branch
+                            && !matches!(
+                                report.outcome,
+                                BranchOutcome::Deleted { .. } | BranchOutcome::Failed(_)
+                            )
+                    })
+                    && matches!(
+                        make_context(branch, &None).determine_branch_status(),
+                        BranchStatus::RemoteBranchExists(_)
+                    )
+            })
+            .map(|(branch, _, last_commit)| StaleBranch {
+                branch,
+                last_commit,
+            })
+            .collect();
+        if !stale_branches.is_empty() {
+            on_event(Event::Stale(&stale_branches));
+        }
+    }
+
+    // a run that stopped early or left failures behind can be resumed
+    if results.len() == local_branches.len() && !results.iter().any(is_failure) {
+        state.finish()?;
+    }
+
+    let any_updated = results.iter().any(|report| {
+        matches!(
+            report.outcome,
+            BranchOutcome::Updated { .. }
+                | BranchOutcome::Rebased { .. }
+                | BranchOutcome::Pulled { .. }
+                | BranchOutcome::Merged { .. }
+        )
+    });
+    if config.update_submodules && any_updated && !config.dry_run {
+        let toplevel = git::get_toplevel()?;
+        if toplevel.join(".gitmodules").exists() {
+            on_event(Event::SubmodulesUpdated(&git::update_submodules()));
+        } else {
+            debug!("No .gitmodules found; skipping submodule update");
+        }
+    }
+
+    Ok(Summary {
+        branches: results,
+        timings,
+    })
+}
+
+// the unix time `age` ago, or 0 for anything older than the epoch
+fn cutoff(age: Duration) -> u64 {
+    SystemTime::now()
+        .checked_sub(age)
+        .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |cutoff| cutoff.as_secs())
+}
+
+// describes the first of --max-deletes and --max-updates that the plan goes over, if any
+fn exceeded_cap(
+    plan: &[PlannedBranch],
+    max_deletes: Option<usize>,
+    max_updates: Option<usize>,
+) -> Option<String> {
+    let deletes = plan
+        .iter()
+        .filter(|planned| matches!(planned.step.action(), Action::Deleted))
+        .count();
+    let updates = plan
+        .iter()
+        .filter(|planned| matches!(planned.step.action(), Action::Updated))
+        .count();
+    let caps = [
+        ("delete", "--max-deletes", deletes, max_deletes),
+        ("update", "--max-updates", updates, max_updates),
+    ];
+    caps.into_iter().find_map(|(verb, flag, count, cap)| {
+        let cap = cap?;
+        (count > cap).then(|| {
+            format!(
+                "Would {} {} branch{}, more than {} {}",
+                verb,
+                count,
+                if count == 1 { "" } else { "es" },
+                flag,
+                cap
+            )
+        })
+    })
+}
+
+// lists every branch that would change with all of them selected; the ones the user deselects
+// are kept as they are
+fn pick_steps(plan: Vec<PlannedBranch>, default_branch: &str) -> Result<Vec<PlannedBranch>> {
+    let mut choices = Vec::new();
+    for (index, planned) in plan.iter().enumerate() {
+        let branch = &planned.branch;
+        let choice = match &planned.step {
+            Step::Done(_) => continue,
+            Step::FastForward { old, new, .. } => {
+                let (_, behind) = git::ahead_behind(old, new)?;
+                format!("update {} ({} behind)", branch, behind)
+            }
+            Step::Pull { .. } => format!("pull {}", branch),
+            Step::Merge { remote_branch, .. } => format!(
+                "merge {} into {}",
+                remote_branch
+                    .strip_prefix("refs/remotes/")
+                    .unwrap_or(remote_branch),
+                branch
+            ),
+            Step::Rebase { old, remote_branch } => {
+                let (ahead, behind) = git::ahead_behind(old, remote_branch)?;
+                format!(
+                    "rebase {} onto {} ({} ahead, {} behind)",
+                    branch,
+                    remote_branch
+                        .strip_prefix("refs/remotes/")
+                        .unwrap_or(remote_branch),
+                    ahead,
+                    behind
+                )
+            }
+            Step::Delete { .. } => format!("delete {}", branch),
+            Step::RebaseGone { .. } => format!("rebase {} onto {}", branch, default_branch),
+        };
+        choices.push((index, choice));
+    }
+    if choices.is_empty() {
+        return Ok(plan);
+    }
+
+    let labels: Vec<&str> = choices.iter().map(|(_, choice)| choice.as_str()).collect();
+    let selected = prompt::pick(&labels)?;
+    let skipped: Vec<usize> = choices
+        .iter()
+        .zip(selected)
+        .filter(|(_, selected)| !selected)
+        .map(|((index, _), _)| *index)
+        .collect();
+
+    Ok(plan
+        .into_iter()
+        .enumerate()
+        .map(|(index, planned)| {
+            if !skipped.contains(&index) {
+                return planned;
+            }
+            debug!("Keeping {} as it is", planned.branch);
+            PlannedBranch {
+                step: Step::Done(BranchOutcome::Skipped {
+                    old: planned.step.old_sha().map(String::from),
+                    reason: report::SkipReason::NotConfirmed,
+                }),
+                branch: planned.branch,
+            }
+        })
+        .collect())
+}
+
+// retries back off exponentially, starting at one second
+fn fetch(remote: &str, timeout: Option<Duration>, retries: usize, args: &[String]) -> Result<()> {
+    info!("Fetching {}", remote);
+    let mut attempt = 0;
+    loop {
+        // an unreachable remote fails the probe in one round-trip instead of a whole fetch, and
+        // is retried like a failed fetch in case the network only blipped
+        let result =
+            git::check_reachable(remote, timeout).and_then(|_| git::fetch(remote, timeout, args));
+        match result {
+            Ok(()) => {
+                info!("Fetched {}", remote);
+                return Ok(());
+            }
+            Err(e) if attempt < retries => {
+                let backoff = Duration::from_secs(1 << attempt);
+                warn!(
+                    "Fetching {} failed ({:#}); retrying in {}s",
+                    remote,
+                    e,
+                    backoff.as_secs()
+                );
+                thread::sleep(backoff);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// only tags that git-up saw on the remote during an earlier run are pruned, so tags that
+// were created locally and never pushed are left alone
+fn prune_tags(remote: &str, dry_run: bool, on_event: &mut impl FnMut(Event)) -> Result<()> {
+    let known_tags_path = git::git_path(&format!("git-up/remote-tags/{}", remote))?;
+    let known_tags = fs::read_to_string(&known_tags_path).unwrap_or_default();
+    let remote_tags = git::get_remote_tags(remote)?;
+    let local_tags = git::get_tags()?;
+
+    let mut pruned_tags: Vec<(&String, &String)> = local_tags
+        .iter()
+        .filter(|(tag, _)| known_tags.lines().any(|known_tag| known_tag == *tag))
+        .filter(|(tag, _)| !remote_tags.contains(tag))
+        .collect();
+    pruned_tags.sort();
+
+    for (tag, sha) in pruned_tags {
+        if dry_run {
+            debug!("Skipping deletion of tag {} in dry-run mode", tag);
+        } else {
+            git::delete_tag(tag)?;
+        }
+        on_event(Event::TagDeleted { tag, sha });
+    }
+
+    if !dry_run {
+        if let Some(parent) = known_tags_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&known_tags_path, remote_tags.join("\n"))?;
+    }
+    Ok(())
+}
+
+// results are returned in the same order as `contexts`, regardless of which thread
+// processed each branch. with `fail_fast`, branches that haven't started yet are dropped
+// once any branch fails
+// each branch comes with its planned step, if there is one
+fn sync_branches(
+    work: Vec<(SyncContext, Option<Step>)>,
+    jobs: usize,
+    fail_fast: bool,
+    state: &State,
+) -> Vec<BranchReport> {
+    let total = work.len();
+    // each worker takes the next branch's step out of its slot
+    let work: Vec<(SyncContext, Mutex<Option<Step>>)> = work
+        .into_iter()
+        .map(|(sync_context, step)| (sync_context, Mutex::new(step)))
+        .collect();
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let results = Mutex::new(Vec::with_capacity(total));
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(total) {
+            scope.spawn(|| loop {
+                if fail_fast && failed.load(Ordering::SeqCst) {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some((sync_context, step)) = work.get(index) else {
+                    break;
+                };
+                let step = step.lock().ok().and_then(|mut step| step.take());
+                let result = sync_branch(sync_context, step);
+                state.record(&result);
+                if is_failure(&result) {
+                    failed.store(true, Ordering::SeqCst);
+                }
+                if let Ok(mut results) = results.lock() {
+                    results.push((index, result));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap_or_default();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+// a planned step is executed as is; without one the branch is planned and executed together
+fn sync_branch(sync_context: &SyncContext, step: Option<Step>) -> BranchReport {
+    let result = match step {
+        Some(step) => execute_step(sync_context, step),
+        None => process_branch(sync_context),
+    };
+    let outcome = result.unwrap_or_else(|e| BranchOutcome::Failed(e.to_string()));
+    match outcome.reason() {
+        Some(reason) => info!(
+            "Branch {}: {} ({})",
+            sync_context.local_branch,
+            outcome.action().as_str(),
+            reason
+        ),
+        None => info!(
+            "Branch {}: {}",
+            sync_context.local_branch,
+            outcome.action().as_str()
+        ),
+    }
+    BranchReport {
+        branch: sync_context.local_branch.clone(),
+        outcome,
+    }
+}
+
+fn is_failure(report: &BranchReport) -> bool {
+    matches!(report.outcome, BranchOutcome::Failed(_))
+}
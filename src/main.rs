@@ -1,194 +1,431 @@
-mod git;
+mod cli;
+mod completions;
+mod format;
+mod progress;
 
-use std::collections::HashMap;
+use std::{
+    env, fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
-use clap::{crate_description, crate_name, crate_version, Command};
+use anyhow::{anyhow, Context, Result};
+use cli::{Cli, ColorChoice, LogFormat};
 use colored::*;
-use log::{debug, info};
+use format::Template;
+use git_up::{
+    report::{self, Action, BranchOutcome, BranchReport, SkipReason},
+    Config, Event, PlannedBranch, StaleBranch,
+};
+use log::warn;
+use progress::Progress;
 
-use anyhow::{Context, Result};
+// returned when branches were updated or deleted, so scripts can tell whether anything changed
+const CHANGED: u8 = 10;
 
-fn main() -> Result<()> {
-    env_logger::init();
+// the most commits --digest lists before summing up the rest
+const DIGEST_LINES: usize = 10;
 
-    Command::new(crate_name!())
-        .version(crate_version!())
-        .about(crate_description!())
-        .get_matches();
+fn main() -> Result<ExitCode> {
+    let started = Instant::now();
+    let cli = Cli::parse();
 
-    let remote = git::get_main_remote()?;
-    let default_branch = git::get_default_branch(&remote)?;
-    let full_default_branch = format!("refs/remotes/{}/{}", remote, default_branch);
+    if let Some(shell) = &cli.completions {
+        print!("{}", completions::generate(shell, cli::command()));
+        return Ok(ExitCode::SUCCESS);
+    }
 
-    git::fetch(&remote).with_context(|| "Failed to execute git fetch command")?;
+    let use_color = match cli.color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && io::stdout().is_terminal()
+        }
+    };
+    colored::control::set_override(use_color);
+    git_up::interrupt::install();
 
-    let branch_remotes_lines = git::get_config(&["--local", "--get-regexp", "branch.*.remote"])
-        .with_context(|| "Failed to execute git config command")?;
+    // RUST_LOG still takes precedence when it is set. the ndjson log is meant for machines,
+    // so it includes progress without needing -v
+    let default_filter = match (cli.verbose, &cli.log_format) {
+        (0, LogFormat::Text) => "warn",
+        (0 | 1, _) => "info",
+        _ => "debug",
+    };
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(default_filter));
+    if let LogFormat::Ndjson = cli.log_format {
+        logger.format(|buf, record| {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            writeln!(
+                buf,
+                "{{\"ts\":{:.3},\"level\":{},\"target\":{},\"message\":{}}}",
+                timestamp,
+                report::json_string(&record.level().to_string().to_lowercase()),
+                report::json_string(record.target()),
+                report::json_string(&record.args().to_string()),
+            )
+        });
+    }
+    logger.init();
 
-    let branches_to_remotes: HashMap<String, String> = branch_remotes_lines
-        .iter()
-        .map(|line| {
-            let parts: Vec<String> = line.split(' ').map(String::from).collect();
-            (
-                parts[0].split('.').skip(1).take(1).collect(),
-                parts[1].clone(),
+    let len = cli.abbrev;
+    if let Some(file) = &cli.undo {
+        let record = fs::read_to_string(file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        git_up::undo(cli.config.directory.as_deref(), &record, |branch, sha| {
+            println!(
+                "{} {}{} at {}.",
+                "Restored branch".green(),
+                branch.green().bold(),
+                "".clear(),
+                abbrev(sha, len),
             )
-        })
-        .collect();
-    debug!("Map of branches to remotes: {:?}", branches_to_remotes);
-
-    let local_branches = git::get_branches().with_context(|| "Failed to get local branches")?;
-
-    for local_branch in local_branches {
-        let current_branch =
-            git::symbolic_ref("HEAD", true).with_context(|| "Failed to get current branch")?;
-        let sync_context = SyncContext {
-            remote: remote.clone(),
-            default_branch: default_branch.clone(),
-            full_default_branch: full_default_branch.clone(),
-            local_branch: local_branch.clone(),
-            current_branch,
-            branches_to_remotes: branches_to_remotes.clone(),
+        })?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let mut repos = cli.directories.clone();
+    if let Some(file) = &cli.repos_file {
+        repos.extend(read_repos_file(file)?);
+    }
+    if repos.len() <= 1 {
+        let config = Config {
+            directory: repos.pop(),
+            ..cli.config.clone()
+        };
+        return Ok(exit_code(sync(&cli, config, started)?));
+    }
+    if cli.write_undo.is_some() {
+        return Err(anyhow!(
+            "--write-undo records a single repository; run git-up once per repository"
+        ));
+    }
+    // neither format says which repository a branch belongs to
+    if cli.json || cli.porcelain {
+        return Err(anyhow!(
+            "--json and --porcelain describe a single repository; run git-up once per repository"
+        ));
+    }
+
+    // each repository gets the whole pipeline in turn. a failure in one is reported and the
+    // rest still run, unless --fail-fast
+    let (mut changed, mut failed, mut synced) = (false, 0, 0);
+    for (index, repo) in repos.iter().enumerate() {
+        let header = format!("==> {}", repo.display()).bold();
+        if index == 0 {
+            println!("{}", header);
+        } else {
+            println!("\n{}", header);
+        }
+        let config = Config {
+            directory: Some(repo.clone()),
+            ..cli.config.clone()
         };
-        let result = process_branch(&sync_context);
-        match result {
-            Ok(_) => {}
+        synced += 1;
+        match sync(&cli, config, Instant::now()) {
+            Ok(repo_changed) => changed |= repo_changed,
             Err(e) => {
-                println!(
-                    "{} {}{} failed to process branch: {}",
-                    "Error:".red(),
-                    local_branch.red().bold(),
-                    "".clear(),
-                    e
-                );
+                eprintln!("{} {}: {:#}", "Error:".red(), repo.display(), e);
+                failed += 1;
+                if cli.config.fail_fast {
+                    break;
+                }
             }
         }
     }
-
-    Ok(())
+    eprintln!(
+        "Synced {} of {} repositories; {} failed.",
+        synced - failed,
+        repos.len(),
+        failed
+    );
+    match failed {
+        0 => Ok(exit_code(changed)),
+        1 => Err(anyhow!("1 repository failed to sync")),
+        failed => Err(anyhow!("{} repositories failed to sync", failed)),
+    }
 }
 
-struct SyncContext {
-    remote: String,
-    default_branch: String,
-    full_default_branch: String,
-    local_branch: String,
-    current_branch: String,
-    branches_to_remotes: HashMap<String, String>,
+fn exit_code(changed: bool) -> ExitCode {
+    if changed {
+        ExitCode::from(CHANGED)
+    } else {
+        ExitCode::SUCCESS
+    }
 }
 
-enum BranchStatus {
-    RemoteBranchExists(String),
-    RemoteBranchGone,
-    Unknown,
+// one path per line, relative to the file's directory. blank lines and lines starting with #
+// are skipped
+fn read_repos_file(file: &Path) -> Result<Vec<PathBuf>> {
+    let contents =
+        fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let base = file.parent().unwrap_or(Path::new(""));
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect())
 }
 
-impl SyncContext {
-    fn determine_branch_status(&self) -> BranchStatus {
-        let SyncContext {
-            remote,
-            local_branch,
-            branches_to_remotes,
-            ..
-        } = self;
-        let remote_branch = format!("refs/remotes/{}/{}", remote, local_branch);
-
-        if let Some(local_branch_remote_name) = branches_to_remotes.get(local_branch) {
-            if local_branch_remote_name == remote {
-                if let Some(symbolic_full_name) =
-                    git::symbolic_full_name(format!("{}@{{upstream}}", local_branch))
-                {
-                    debug!("Symbolic full name is {}", symbolic_full_name);
-                    BranchStatus::RemoteBranchExists(symbolic_full_name)
-                } else {
-                    debug!("No symbolic full name found for {}", local_branch);
-                    BranchStatus::RemoteBranchGone
-                }
-            } else if !git::has_file(&remote_branch) {
-                BranchStatus::Unknown
-            } else {
-                BranchStatus::RemoteBranchExists(remote_branch.clone())
+// runs git-up against one repository, printing as it goes. returns whether any branch changed
+fn sync(cli: &Cli, config: Config, started: Instant) -> Result<bool> {
+    let len = cli.abbrev;
+    let json = cli.json;
+    // either machine-readable format replaces the human-readable output
+    let machine = json || cli.porcelain;
+    let quiet = cli.quiet;
+    let show_stat = cli.show_stat;
+    let warn_errors = cli.warnings_as_errors;
+    let fail_on_protected = cli.fail_on_protected;
+    let dry_run = config.dry_run;
+    let suffix = if dry_run { " (dry-run)" } else { "" };
+    // log lines and per-branch prompts would land in the middle of the status line, so it's
+    // left out when either is possible
+    let mut progress = Progress::new(
+        !machine
+            && !quiet
+            && !config.confirm
+            && !config.confirm_deletes
+            && cli.verbose == 0
+            && matches!(cli.log_format, LogFormat::Text),
+    );
+    let summary = git_up::run_with(config, |event| {
+        if let Event::Processing {
+            branch,
+            current,
+            total,
+        } = event
+        {
+            progress.show(&format!(
+                "Processing branch {} of {} ({})",
+                current, total, branch
+            ));
+            return;
+        }
+        progress.clear();
+        // with --group, branches wait until the run is over
+        if cli.group && matches!(event, Event::Branch(_)) {
+            return;
+        }
+        // the plan is shown even under --quiet, since it's what the prompt asks about
+        if !machine && (!quiet || is_problem(&event) || matches!(event, Event::Plan(_))) {
+            print_event(&event, &cli.format, suffix, show_stat, len);
+        }
+    });
+    progress.clear();
+    let summary = summary?;
+
+    if cli.group && !machine {
+        let mut reports: Vec<&BranchReport> = summary.branches.iter().collect();
+        reports.sort_by_key(|report| (group_order(&report.outcome.action()), &report.branch));
+        for report in reports {
+            let event = Event::Branch(report);
+            if !quiet || is_problem(&event) {
+                print_event(&event, &cli.format, suffix, show_stat, len);
             }
-        } else {
-            BranchStatus::RemoteBranchExists(remote_branch.clone())
         }
     }
+
+    if let Some(file) = &cli.write_undo {
+        if !dry_run {
+            fs::write(file, git_up::undo_record(&summary.branches))
+                .with_context(|| format!("Failed to write {}", file.display()))?;
+        }
+    }
+
+    if json {
+        println!("{}", report::to_json(&summary.branches));
+    } else if cli.porcelain {
+        print!("{}", report::to_porcelain(&summary.branches));
+    }
+
+    if cli.timings {
+        eprintln!(
+            "Timings: fetch {:.2}s, branches {:.2}s, total {:.2}s",
+            summary.timings.fetch.as_secs_f64(),
+            summary.timings.branches.as_secs_f64(),
+            started.elapsed().as_secs_f64(),
+        );
+    }
+
+    let changed = !dry_run
+        && summary
+            .branches
+            .iter()
+            .any(|report| matches!(report.outcome.action(), Action::Updated | Action::Deleted));
+    let warnings = summary.warnings();
+    let spared = summary.spared();
+    match summary.failures() {
+        0 if warn_errors && warnings == 1 => Err(anyhow!("1 branch has a warning")),
+        0 if warn_errors && warnings > 1 => Err(anyhow!("{} branches have warnings", warnings)),
+        0 if fail_on_protected && spared == 1 => Err(anyhow!(
+            "1 protected branch is merged and its upstream is gone"
+        )),
+        0 if fail_on_protected && spared > 1 => Err(anyhow!(
+            "{} protected branches are merged and their upstreams are gone",
+            spared
+        )),
+        0 => Ok(changed),
+        1 => Err(anyhow!("1 branch failed to sync")),
+        failures => Err(anyhow!("{} branches failed to sync", failures)),
+    }
+}
+
+// shortens a sha for display. anything already shorter is shown whole rather than panicking
+fn abbrev(sha: &str, len: usize) -> &str {
+    sha.get(..len).unwrap_or(sha)
+}
+
+// where branches land in --group output
+fn group_order(action: &Action) -> u8 {
+    match action {
+        Action::Updated => 0,
+        Action::Deleted => 1,
+        Action::Warning => 2,
+        Action::Skipped => 3,
+        Action::Error => 4,
+    }
 }
 
-fn process_branch(sync_context: &SyncContext) -> Result<()> {
-    let SyncContext {
-        remote,
-        default_branch,
-        full_default_branch,
-        local_branch,
-        current_branch,
-        ..
-    } = sync_context;
-    let full_branch = format!("refs/heads/{}", local_branch);
-
-    info!("Checking branch {}", local_branch);
-    let branch_status = sync_context.determine_branch_status();
-
-    match branch_status {
-        BranchStatus::RemoteBranchExists(remote_branch) => {
-            let range = git::make_range(&full_branch, &remote_branch)?;
-
-            if range.is_identical() {
-                Ok(())
-            } else if range.is_ancestor() {
-                if local_branch == current_branch {
-                    git::fast_forward_merge(&remote_branch)
-                        .with_context(|| "failed to fast forward merge")?;
-                } else {
-                    git::update_ref(&full_branch, &remote_branch)
-                        .with_context(|| "failed to update ref")?;
+// warnings and errors are the only output left under --quiet
+fn is_problem(event: &Event) -> bool {
+    match event {
+        Event::Branch(report) => matches!(
+            report.outcome,
+            BranchOutcome::Warned { .. }
+                | BranchOutcome::Failed(_)
+                | BranchOutcome::Skipped {
+                    reason: SkipReason::LinkedWorktree(_)
+                        | SkipReason::PreDeleteHook
+                        | SkipReason::BackupFailed(_),
+                    ..
                 }
-                println!(
-                    "{} {}{} (was {}).",
-                    "Updated branch".green(),
-                    local_branch.green().bold(),
-                    "".clear(),
-                    &range.a[0..7],
-                );
-                Ok(())
-            } else {
-                println!(
-                    "{} {}{} seems to contain unpushed commits",
-                    "Warning:".yellow(),
-                    local_branch.yellow().bold(),
+        ),
+        Event::SubmodulesUpdated(result) => result.is_err(),
+        _ => false,
+    }
+}
+
+fn print_event(event: &Event, format: &Template, suffix: &str, show_stat: bool, len: usize) {
+    match event {
+        // anything that isn't the result of syncing goes to stderr, so stdout can be piped
+        Event::NoRemotes => eprintln!("No remotes configured; nothing to sync."),
+        Event::NoFetchUrl => eprintln!("No remotes have a fetch URL configured; nothing to sync."),
+        Event::NotOnBranch => eprintln!("Not on a branch; nothing to sync."),
+        Event::NoCommits => eprintln!("No commits yet; nothing to sync."),
+        Event::RemoteHeadSet { remote, branch } => eprintln!(
+            "{} {}/HEAD{} to {}.",
+            "Set".green(),
+            remote.green().bold(),
+            "".clear(),
+            branch,
+        ),
+        Event::TagDeleted { tag, sha } => println!(
+            "{} {}{} (was {}).{}",
+            "Deleted tag".red(),
+            tag.red().bold(),
+            "".clear(),
+            abbrev(sha, len),
+            suffix,
+        ),
+        Event::Digest { upstream, commits } => print_digest(upstream, commits),
+        Event::Plan(plan) => print_plan(plan),
+        Event::Processing { .. } => {}
+        Event::Branch(report) => print_branch(report, format, suffix, show_stat, len),
+        Event::Stale(branches) => print_stale(branches),
+        Event::SubmodulesUpdated(Ok(_)) => println!("{}", "Updated submodules.".green()),
+        Event::SubmodulesUpdated(Err(e)) => {
+            println!("{} failed to update submodules: {}", "Error:".red(), e)
+        }
+    }
+}
+
+fn print_digest(upstream: &str, commits: &[String]) {
+    eprintln!(
+        "{} {}{}:",
+        "New on".cyan(),
+        upstream.cyan().bold(),
+        "".clear()
+    );
+    for commit in commits.iter().take(DIGEST_LINES) {
+        eprintln!("  {}", commit);
+    }
+    if commits.len() > DIGEST_LINES {
+        eprintln!("  ... and {} more", commits.len() - DIGEST_LINES);
+    }
+}
+
+fn print_stale(branches: &[StaleBranch]) {
+    eprintln!("{}", "Stale branches:".yellow());
+    for stale in branches {
+        eprintln!(
+            "  {}{} (last commit {})",
+            stale.branch.yellow().bold(),
+            "".clear(),
+            stale.last_commit
+        );
+    }
+}
+
+fn print_plan(plan: &[PlannedBranch]) {
+    let (mut updates, mut deletes, mut warnings) = (0, 0, 0);
+    for planned in plan {
+        let branch = &planned.branch;
+        match planned.step.action() {
+            Action::Updated => {
+                updates += 1;
+                eprintln!(
+                    "{} {}{}.",
+                    "Will update branch".green(),
+                    branch.green().bold(),
                     "".clear()
                 );
-                Ok(())
             }
-        }
-        BranchStatus::RemoteBranchGone => {
-            let range = git::make_range(&full_branch, full_default_branch)?;
-            if range.is_ancestor() {
-                if local_branch == current_branch {
-                    git::checkout(default_branch)
-                        .with_context(|| "failed to checkout default branch")?;
-                }
-                git::delete_branch(local_branch)
-                    .with_context(|| "failed to delete local branch")?;
-                println!(
-                    "{} {}{} (was {}).",
-                    "Deleted branch".red(),
-                    local_branch.red().bold(),
-                    "".clear(),
-                    &range.a[0..7],
-                );
-            } else {
-                println!(
-                    "{} {}{} was deleted on {}, but appears not merged into {}",
-                    "Warning:".yellow(),
-                    local_branch.yellow().bold(),
-                    "".clear(),
-                    remote,
-                    default_branch.bold(),
+            Action::Deleted => {
+                deletes += 1;
+                eprintln!(
+                    "{} {}{}.",
+                    "Will delete branch".red(),
+                    branch.red().bold(),
+                    "".clear()
                 );
             }
-            Ok(())
+            Action::Warning => warnings += 1,
+            Action::Skipped | Action::Error => {}
+        }
+    }
+    eprintln!(
+        "{} to update, {} to delete, {} warning{}.",
+        updates,
+        deletes,
+        warnings,
+        if warnings == 1 { "" } else { "s" }
+    );
+}
+
+fn print_branch(
+    report: &BranchReport,
+    format: &Template,
+    suffix: &str,
+    show_stat: bool,
+    len: usize,
+) {
+    let Some(line) = format.render(report, suffix, len) else {
+        return;
+    };
+    println!("{}", line);
+    if let (BranchOutcome::Updated { old, new }, true) = (&report.outcome, show_stat) {
+        match git_up::git::diff_stat(old, new) {
+            Ok(stat) => stat.lines().for_each(|line| println!("   {}", line)),
+            Err(e) => warn!("Failed to show what changed on {}: {:#}", report.branch, e),
         }
-        BranchStatus::Unknown => Ok(()),
     }
 }
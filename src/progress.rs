@@ -0,0 +1,36 @@
+use std::io::{self, IsTerminal, Write};
+
+// erases the current terminal line and moves the cursor back to its start
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+// a single status line on stderr, redrawn in place. it's erased before anything else is
+// printed so output never ends up on the same line as the status
+pub struct Progress {
+    enabled: bool,
+    shown: bool,
+}
+
+impl Progress {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: enabled && io::stderr().is_terminal(),
+            shown: false,
+        }
+    }
+
+    pub fn show(&mut self, status: &str) {
+        if self.enabled {
+            eprint!("{}{}", CLEAR_LINE, status);
+            let _ = io::stderr().flush();
+            self.shown = true;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        if self.shown {
+            eprint!("{}", CLEAR_LINE);
+            let _ = io::stderr().flush();
+            self.shown = false;
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::{Context, Result};
+
+// asks a yes/no question on stderr; anything other than "y" or "yes" counts as no
+pub fn confirm(question: &str) -> Result<bool> {
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+
+    let answer = read_answer()?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+// lists the choices on stderr, all selected, and asks which to leave out. returns whether
+// each choice is still selected
+pub fn pick(choices: &[&str]) -> Result<Vec<bool>> {
+    for (index, choice) in choices.iter().enumerate() {
+        eprintln!("  {}) {}", index + 1, choice);
+    }
+    loop {
+        eprint!("Numbers to skip (e.g. 1 3), or enter to continue with all: ");
+        io::stderr().flush()?;
+
+        let answer = read_answer()?;
+        match parse_selection(&answer, choices.len()) {
+            Some(selected) => return Ok(selected),
+            None => eprintln!("Enter numbers between 1 and {}.", choices.len()),
+        }
+    }
+}
+
+fn read_answer() -> Result<String> {
+    // at the end of input this is empty, which callers take as the default answer
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .with_context(|| "Failed to read answer from stdin")?;
+    Ok(answer)
+}
+
+// numbers may be separated by spaces or commas
+fn parse_selection(answer: &str, count: usize) -> Option<Vec<bool>> {
+    let mut selected = vec![true; count];
+    for number in answer
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|number| !number.is_empty())
+    {
+        let index = number
+            .parse::<usize>()
+            .ok()
+            .filter(|number| (1..=count).contains(number))?;
+        selected[index - 1] = false;
+    }
+    Some(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_skipped_choices() {
+        assert_eq!(parse_selection("\n", 3), Some(vec![true, true, true]));
+        assert_eq!(parse_selection("1, 3\n", 3), Some(vec![false, true, false]));
+        assert_eq!(parse_selection("4", 3), None);
+        assert_eq!(parse_selection("two", 3), None);
+    }
+}
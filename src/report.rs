@@ -0,0 +1,267 @@
+use std::fmt::{self, Write};
+
+pub enum Action {
+    Updated,
+    Deleted,
+    Skipped,
+    Warning,
+    Error,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Updated => "updated",
+            Action::Deleted => "deleted",
+            Action::Skipped => "skipped",
+            Action::Warning => "warning",
+            Action::Error => "error",
+        }
+    }
+}
+
+pub enum SkipReason {
+    Protected,
+    NotConfirmed,
+    LinkedWorktree(String),
+    NoRemoteBranch,
+    Inactive,
+    PreDeleteHook,
+    // pushing the branch to --backup-remote failed, so it wasn't deleted
+    BackupFailed(String),
+    // run as `git-up clean`, which leaves branches it wouldn't delete alone
+    DeleteOnly,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::Protected => write!(f, "protected"),
+            SkipReason::NotConfirmed => write!(f, "deletion not confirmed"),
+            SkipReason::LinkedWorktree(path) => write!(f, "checked out in worktree at {}", path),
+            SkipReason::NoRemoteBranch => write!(f, "no remote branch found"),
+            SkipReason::Inactive => write!(f, "no commits since the cutoff"),
+            SkipReason::PreDeleteHook => write!(f, "pre-delete hook failed"),
+            SkipReason::BackupFailed(remote) => write!(f, "backup to {} failed", remote),
+            SkipReason::DeleteOnly => write!(f, "only deleting branches"),
+        }
+    }
+}
+
+// what happened to a single branch. new shas are None when nothing was written, e.g. in
+// dry-run mode
+pub enum BranchOutcome {
+    UpToDate {
+        sha: String,
+    },
+    Updated {
+        old: String,
+        new: String,
+    },
+    Rebased {
+        old: String,
+        new: Option<String>,
+        onto: String,
+    },
+    Pulled {
+        old: String,
+        new: Option<String>,
+    },
+    Merged {
+        old: String,
+        new: Option<String>,
+        from: String,
+    },
+    Deleted {
+        old: String,
+    },
+    Warned {
+        old: String,
+        new: Option<String>,
+        reason: String,
+    },
+    Skipped {
+        old: Option<String>,
+        reason: SkipReason,
+    },
+    Failed(String),
+}
+
+impl BranchOutcome {
+    pub fn action(&self) -> Action {
+        match self {
+            BranchOutcome::Updated { .. }
+            | BranchOutcome::Rebased { .. }
+            | BranchOutcome::Pulled { .. }
+            | BranchOutcome::Merged { .. } => Action::Updated,
+            BranchOutcome::Deleted { .. } => Action::Deleted,
+            BranchOutcome::UpToDate { .. } | BranchOutcome::Skipped { .. } => Action::Skipped,
+            BranchOutcome::Warned { .. } => Action::Warning,
+            BranchOutcome::Failed(_) => Action::Error,
+        }
+    }
+
+    pub fn old_sha(&self) -> Option<&str> {
+        match self {
+            BranchOutcome::UpToDate { sha } => Some(sha),
+            BranchOutcome::Updated { old, .. }
+            | BranchOutcome::Rebased { old, .. }
+            | BranchOutcome::Pulled { old, .. }
+            | BranchOutcome::Merged { old, .. }
+            | BranchOutcome::Deleted { old }
+            | BranchOutcome::Warned { old, .. } => Some(old),
+            BranchOutcome::Skipped { old, .. } => old.as_deref(),
+            BranchOutcome::Failed(_) => None,
+        }
+    }
+
+    pub fn new_sha(&self) -> Option<&str> {
+        match self {
+            BranchOutcome::UpToDate { sha } => Some(sha),
+            BranchOutcome::Updated { new, .. } => Some(new),
+            BranchOutcome::Rebased { new, .. }
+            | BranchOutcome::Pulled { new, .. }
+            | BranchOutcome::Merged { new, .. }
+            | BranchOutcome::Warned { new, .. } => new.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn reason(&self) -> Option<String> {
+        match self {
+            BranchOutcome::UpToDate { .. } => Some("up to date".to_string()),
+            BranchOutcome::Rebased { onto, .. } => Some(format!("rebased onto {}", onto)),
+            BranchOutcome::Pulled { .. } => Some("pulled".to_string()),
+            BranchOutcome::Merged { from, .. } => Some(format!("merged {}", from)),
+            BranchOutcome::Warned { reason, .. } | BranchOutcome::Failed(reason) => {
+                Some(reason.clone())
+            }
+            BranchOutcome::Skipped { reason, .. } => Some(reason.to_string()),
+            BranchOutcome::Updated { .. } | BranchOutcome::Deleted { .. } => None,
+        }
+    }
+}
+
+pub struct BranchReport {
+    pub branch: String,
+    pub outcome: BranchOutcome,
+}
+
+pub fn to_json(reports: &[BranchReport]) -> String {
+    let entries: Vec<String> = reports.iter().map(report_json).collect();
+    format!("[{}]", entries.join(","))
+}
+
+pub fn report_json(report: &BranchReport) -> String {
+    format!(
+        "{{\"branch\":{},\"action\":{},\"old_sha\":{},\"new_sha\":{},\"reason\":{}}}",
+        json_string(&report.branch),
+        json_string(report.outcome.action().as_str()),
+        json_option(report.outcome.old_sha()),
+        json_option(report.outcome.new_sha()),
+        json_option(report.outcome.reason().as_deref()),
+    )
+}
+
+// `<action> <branch> <old-sha> <new-sha>` per line, for --porcelain=v1. the action words
+// differ from `Action` so that up-to-date and skipped branches can be told apart, and none of
+// them may change
+pub fn to_porcelain(reports: &[BranchReport]) -> String {
+    reports
+        .iter()
+        .map(|report| {
+            let action = match &report.outcome {
+                BranchOutcome::Updated { .. }
+                | BranchOutcome::Rebased { .. }
+                | BranchOutcome::Pulled { .. }
+                | BranchOutcome::Merged { .. } => "updated",
+                BranchOutcome::Deleted { .. } => "deleted",
+                BranchOutcome::Warned { .. } => "warn",
+                BranchOutcome::UpToDate { .. } => "noop",
+                BranchOutcome::Skipped { .. } => "skip",
+                BranchOutcome::Failed(_) => "error",
+            };
+            format!(
+                "{} {} {} {}\n",
+                action,
+                report.branch,
+                report.outcome.old_sha().unwrap_or("-"),
+                report.outcome.new_sha().unwrap_or("-"),
+            )
+        })
+        .collect()
+}
+
+fn json_option(value: Option<&str>) -> String {
+    match value {
+        Some(value) => json_string(value),
+        None => "null".to_string(),
+    }
+}
+
+pub fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_json_strings() {
+        assert_eq!(json_string("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+
+    #[test]
+    fn serializes_reports() {
+        let reports = [BranchReport {
+            branch: "gone".to_string(),
+            outcome: BranchOutcome::Skipped {
+                old: None,
+                reason: SkipReason::NoRemoteBranch,
+            },
+        }];
+        assert_eq!(
+            to_json(&reports),
+            "[{\"branch\":\"gone\",\"action\":\"skipped\",\"old_sha\":null,\"new_sha\":null,\"reason\":\"no remote branch found\"}]"
+        );
+    }
+
+    #[test]
+    fn formats_porcelain_lines() {
+        let reports = [
+            BranchReport {
+                branch: "main".to_string(),
+                outcome: BranchOutcome::UpToDate {
+                    sha: "abc".to_string(),
+                },
+            },
+            BranchReport {
+                branch: "gone".to_string(),
+                outcome: BranchOutcome::Deleted {
+                    old: "def".to_string(),
+                },
+            },
+        ];
+        assert_eq!(
+            to_porcelain(&reports),
+            "noop main abc abc\ndeleted gone def -\n"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use log::{debug, warn};
+
+use crate::{
+    git,
+    report::{self, BranchOutcome, BranchReport},
+};
+
+const STATE_FILE: &str = "git-up-state.json";
+
+// branches handled so far by a resumable run, kept in .git/git-up-state.json so an interrupted
+// run can be picked up again. nothing is kept unless --resume or git-up.resume asks for it.
+// each report is appended as its own line as the branch is handled:
+//
+//     {"branch":"main","action":"skipped","old_sha":"5f3a1c2...","new_sha":"5f3a1c2...","reason":"up to date"}
+//     {"branch":"merged","action":"deleted","old_sha":"9b8e7d6...","new_sha":null,"reason":null}
+//
+// failed branches aren't recorded, so resuming tries them again. once every branch has been
+// handled the file is removed
+pub struct State {
+    path: PathBuf,
+    handled: Vec<String>,
+    // None when progress isn't kept, and for dry runs, which can skip what was handled but
+    // don't record anything themselves
+    file: Option<Mutex<File>>,
+}
+
+impl State {
+    pub fn open(resume: bool, read_only: bool) -> Result<Self> {
+        let path = git::git_path(STATE_FILE)?;
+        if !resume {
+            return Ok(Self {
+                path,
+                handled: Vec::new(),
+                file: None,
+            });
+        }
+
+        let handled = match fs::read_to_string(&path) {
+            Ok(contents) => {
+                debug!("Resuming from {}", path.display());
+                contents.lines().filter_map(branch_of).collect()
+            }
+            Err(_) => Vec::new(),
+        };
+        let file = if read_only {
+            None
+        } else {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            Some(Mutex::new(file))
+        };
+        Ok(Self {
+            path,
+            handled,
+            file,
+        })
+    }
+
+    pub fn handled_branches(&self) -> Vec<String> {
+        self.handled.clone()
+    }
+
+    // losing track of progress shouldn't stop the run, so failures here are only logged
+    pub fn record(&self, report: &BranchReport) {
+        if matches!(report.outcome, BranchOutcome::Failed(_)) {
+            return;
+        }
+        let Some(Ok(mut file)) = self.file.as_ref().map(Mutex::lock) else {
+            return;
+        };
+        if let Err(e) = writeln!(file, "{}", report::report_json(report)) {
+            warn!("Failed to write {}: {}", self.path.display(), e);
+        }
+    }
+
+    pub fn finish(self) -> Result<()> {
+        if self.file.is_none() {
+            return Ok(());
+        }
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("Failed to remove {}", self.path.display())),
+        }
+    }
+}
+
+fn branch_of(entry: &str) -> Option<String> {
+    let rest = entry.strip_prefix("{\"branch\":\"")?;
+    // the name ends at the first quote that isn't escaped
+    let mut escaped = false;
+    let end = rest.char_indices().find_map(|(index, c)| match c {
+        '\\' if !escaped => {
+            escaped = true;
+            None
+        }
+        '"' if !escaped => Some(index),
+        _ => {
+            escaped = false;
+            None
+        }
+    })?;
+    unescape(&rest[..end])
+}
+
+// the inverse of report::json_string, for the escapes it produces
+fn unescape(value: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '"' => unescaped.push('"'),
+            '\\' => unescaped.push('\\'),
+            'n' => unescaped.push('\n'),
+            'r' => unescaped.push('\r'),
+            't' => unescaped.push('\t'),
+            'u' => {
+                let code: String = chars.by_ref().take(4).collect();
+                unescaped.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+            }
+            _ => return None,
+        }
+    }
+    Some(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_branches_back_from_entries() {
+        let contents = "\
+             {\"branch\":\"main\",\"action\":\"skipped\",\"old_sha\":null,\"new_sha\":null,\"reason\":null}\n\
+             {\"branch\":\"odd\\\"name\",\"action\":\"deleted\",\"old_sha\":null,\"new_sha\":null,\"reason\":null}\n";
+        let branches: Vec<String> = contents.lines().filter_map(branch_of).collect();
+
+        assert_eq!(branches, ["main", "odd\"name"]);
+    }
+
+    #[test]
+    fn unescapes_json_strings() {
+        assert_eq!(
+            unescape(r#"a\"b\\c\n\u0001"#).as_deref(),
+            Some("a\"b\\c\n\u{1}")
+        );
+        assert_eq!(unescape("trailing\\"), None);
+    }
+}
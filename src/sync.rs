@@ -0,0 +1,699 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use log::{debug, info};
+
+use crate::{
+    git, pattern, prompt,
+    report::{Action, BranchOutcome, SkipReason},
+};
+
+// what to do when the checked-out branch has diverged from its upstream
+#[derive(Clone, Copy)]
+pub enum OnConflict {
+    Warn,
+    Rebase,
+    Merge,
+    // stop before anything is changed; handled in `run`, so planning treats it like `Warn`
+    Abort,
+}
+
+pub struct SyncContext {
+    pub remote: String,
+    pub default_branch: String,
+    pub full_default_branch: String,
+    pub local_branch: String,
+    pub current_branch: Option<String>,
+    pub branches_to_remotes: HashMap<String, String>,
+    pub synced_remotes: Vec<String>,
+    // every configured remote, not just the ones that were fetched
+    pub remotes: Vec<String>,
+    pub protected_branches: Vec<String>,
+    pub worktree_branches: HashMap<String, String>,
+    pub refs: git::Refs,
+    pub rebase: bool,
+    pub pull: bool,
+    pub autostash: bool,
+    pub detect_squash: bool,
+    pub rebase_gone: bool,
+    pub prune_merged: bool,
+    // compare against the local default branch only; nothing is fetched or tracked
+    pub local_only: bool,
+    pub archive: bool,
+    pub backup_remote: Option<String>,
+    // delete without checking again that the branch is merged into the default branch
+    pub force: bool,
+    // a merged current branch is kept and warned about instead of checked out of and deleted
+    pub keep_current: bool,
+    pub delete_only: bool,
+    pub on_conflict: OnConflict,
+    // shell command from git-up.preDelete; a non-zero exit keeps the branch
+    pub pre_delete: Option<String>,
+    // branches whose tip was committed before this time, in seconds since the epoch, are skipped
+    pub since: Option<u64>,
+    pub confirm: bool,
+    // branches that would be deleted are kept, as if the user had said no
+    pub keep_deletes: bool,
+    pub dry_run: bool,
+}
+
+pub enum BranchStatus {
+    RemoteBranchExists(String),
+    RemoteBranchGone,
+    // branch.<name>.remote names a remote that has since been removed
+    RemoteRemoved(String),
+    Unknown,
+}
+
+impl SyncContext {
+    // branches checked out in another worktree can't be moved or deleted from here
+    fn linked_worktree_path(&self) -> Option<&String> {
+        if self.is_current_branch() {
+            None
+        } else {
+            self.worktree_branches.get(&self.local_branch)
+        }
+    }
+
+    fn is_current_branch(&self) -> bool {
+        self.current_branch.as_ref() == Some(&self.local_branch)
+    }
+
+    // mirrors `git pull`, which rebases branches configured with branch.<name>.rebase
+    fn has_rebase_config(&self) -> Result<bool> {
+        let values = git::get_config(&["--get", &format!("branch.{}.rebase", self.local_branch)])
+            .with_context(|| "Failed to execute git config command")?;
+        Ok(values.first().is_some_and(|value| value != "false"))
+    }
+
+    fn is_protected(&self) -> bool {
+        pattern::matches_any(&self.protected_branches, &self.local_branch)
+    }
+
+    pub fn determine_branch_status(&self) -> BranchStatus {
+        let SyncContext {
+            remote,
+            default_branch,
+            full_default_branch,
+            synced_remotes,
+            remotes,
+            local_branch,
+            branches_to_remotes,
+            refs,
+            ..
+        } = self;
+        let full_branch = format!("refs/heads/{}", local_branch);
+        let remote_branch = format!("refs/remotes/{}/{}", remote, local_branch);
+
+        if let Some(local_branch_remote_name) = branches_to_remotes.get(local_branch) {
+            if local_branch_remote_name == "." {
+                // branch.<name>.remote = "." means the upstream is another local branch
+                match refs.upstream(&full_branch) {
+                    Some(upstream) if refs.sha(upstream).is_some() => {
+                        debug!("Local upstream of {} is {}", local_branch, upstream);
+                        BranchStatus::RemoteBranchExists(upstream.clone())
+                    }
+                    _ => {
+                        debug!("Local upstream of {} is missing", local_branch);
+                        BranchStatus::Unknown
+                    }
+                }
+            } else if !remotes.contains(local_branch_remote_name) {
+                debug!(
+                    "{} tracks {}, which is not a configured remote",
+                    local_branch, local_branch_remote_name
+                );
+                BranchStatus::RemoteRemoved(local_branch_remote_name.clone())
+            } else if synced_remotes.contains(local_branch_remote_name) {
+                // the ref is checked again, since `refs` may predate a prune that removed it
+                match refs.upstream(&full_branch) {
+                    Some(upstream) if refs.sha(upstream).is_some() && git::has_ref(upstream) => {
+                        debug!("Upstream of {} is {}", local_branch, upstream);
+                        BranchStatus::RemoteBranchExists(upstream.clone())
+                    }
+                    _ => {
+                        debug!("No upstream found for {}", local_branch);
+                        BranchStatus::RemoteBranchGone
+                    }
+                }
+            } else if !git::has_file(&remote_branch) {
+                BranchStatus::Unknown
+            } else {
+                BranchStatus::RemoteBranchExists(remote_branch.clone())
+            }
+        } else if local_branch == default_branch && git::has_ref(full_default_branch) {
+            // the default branch follows the remote it was looked up on, even without an
+            // explicit branch.<default>.remote
+            BranchStatus::RemoteBranchExists(full_default_branch.clone())
+        } else if git::has_ref(&remote_branch) {
+            BranchStatus::RemoteBranchExists(remote_branch.clone())
+        } else {
+            // with no upstream configured, a missing remote branch most likely was never pushed,
+            // so it isn't treated as gone
+            debug!(
+                "No upstream configured for {} and {} is missing",
+                local_branch, remote_branch
+            );
+            BranchStatus::Unknown
+        }
+    }
+}
+
+// concurrent ref writes contend for packed-refs.lock, so they are serialized
+static MUTATION_LOCK: Mutex<()> = Mutex::new(());
+
+// what should happen to a branch, decided before anything is written so a whole run can be
+// reviewed up front. `Done` covers branches that need no changes
+pub enum Step {
+    Done(BranchOutcome),
+    FastForward {
+        old: String,
+        new: String,
+        remote_branch: String,
+    },
+    Pull {
+        old: String,
+    },
+    Rebase {
+        old: String,
+        remote_branch: String,
+    },
+    Merge {
+        old: String,
+        remote_branch: String,
+    },
+    // `squashed` branches were merged by a squash, which an ancestry check can't see
+    Delete {
+        old: String,
+        squashed: bool,
+    },
+    RebaseGone {
+        old: String,
+    },
+}
+
+pub struct PlannedBranch {
+    pub branch: String,
+    pub step: Step,
+}
+
+impl Step {
+    pub fn old_sha(&self) -> Option<&str> {
+        match self {
+            Step::Done(outcome) => outcome.old_sha(),
+            Step::FastForward { old, .. }
+            | Step::Pull { old }
+            | Step::Rebase { old, .. }
+            | Step::Merge { old, .. }
+            | Step::Delete { old, .. }
+            | Step::RebaseGone { old } => Some(old),
+        }
+    }
+
+    pub fn action(&self) -> Action {
+        match self {
+            Step::Done(outcome) => outcome.action(),
+            Step::FastForward { .. }
+            | Step::Pull { .. }
+            | Step::Rebase { .. }
+            | Step::Merge { .. }
+            | Step::RebaseGone { .. } => Action::Updated,
+            Step::Delete { .. } => Action::Deleted,
+        }
+    }
+}
+
+pub fn process_branch(sync_context: &SyncContext) -> Result<BranchOutcome> {
+    let step = plan_branch(sync_context)?;
+    execute_step(sync_context, step)
+}
+
+pub fn plan_branch(sync_context: &SyncContext) -> Result<Step> {
+    let SyncContext {
+        remote,
+        branches_to_remotes,
+        default_branch,
+        full_default_branch,
+        local_branch,
+        refs,
+        rebase,
+        pull,
+        detect_squash,
+        rebase_gone,
+        prune_merged,
+        local_only,
+        keep_current,
+        delete_only,
+        on_conflict,
+        since,
+        ..
+    } = sync_context;
+    let full_branch = format!("refs/heads/{}", local_branch);
+
+    if let Some(cutoff) = since {
+        if refs
+            .committer_date(&full_branch)
+            .is_some_and(|date| date < *cutoff)
+        {
+            debug!("Skipping {}; no commits since the cutoff", local_branch);
+            return Ok(Step::Done(BranchOutcome::Skipped {
+                old: refs.sha(&full_branch).cloned(),
+                reason: SkipReason::Inactive,
+            }));
+        }
+    }
+
+    info!("Checking branch {}", local_branch);
+    if *local_only {
+        return plan_local_branch(sync_context, &full_branch);
+    }
+    let branch_status = sync_context.determine_branch_status();
+
+    match branch_status {
+        BranchStatus::RemoteBranchExists(remote_branch) => {
+            let range = refs.range(&full_branch, &remote_branch)?;
+
+            // branches merged into the default branch whose upstream hasn't been deleted yet
+            if *prune_merged
+                && !sync_context.is_current_branch()
+                && !sync_context.is_protected()
+                && refs.range(&full_branch, full_default_branch)?.is_ancestor()
+            {
+                return Ok(plan_delete(sync_context, range.a, false));
+            }
+            if *delete_only {
+                return Ok(Step::Done(BranchOutcome::Skipped {
+                    old: Some(range.a),
+                    reason: SkipReason::DeleteOnly,
+                }));
+            }
+
+            if range.is_identical() {
+                Ok(Step::Done(BranchOutcome::UpToDate { sha: range.a }))
+            } else if range.is_ancestor()
+                && sync_context.is_current_branch()
+                && !sync_context.autostash
+                && git::has_uncommitted_changes()?
+            {
+                Ok(Step::Done(BranchOutcome::Warned {
+                    old: range.a,
+                    new: Some(range.b),
+                    reason: "is checked out with uncommitted changes; skipping fast-forward \
+                             (commit or stash them, or use --autostash)"
+                        .to_string(),
+                }))
+            } else if range.is_ancestor() {
+                Ok(Step::FastForward {
+                    old: range.a,
+                    new: range.b,
+                    remote_branch,
+                })
+            } else if sync_context.is_current_branch() && *pull && !range.is_descendant() {
+                Ok(Step::Pull { old: range.a })
+            } else if sync_context.is_current_branch()
+                && (*rebase
+                    || matches!(on_conflict, OnConflict::Rebase)
+                    || sync_context.has_rebase_config()?)
+                && !range.is_descendant()
+            {
+                Ok(Step::Rebase {
+                    old: range.a,
+                    remote_branch,
+                })
+            } else if sync_context.is_current_branch()
+                && matches!(on_conflict, OnConflict::Merge)
+                && !range.is_descendant()
+            {
+                Ok(Step::Merge {
+                    old: range.a,
+                    remote_branch,
+                })
+            } else {
+                // anything only behind its upstream was fast-forwarded above
+                debug_assert!(!range.is_ancestor());
+                let (ahead, behind) = git::ahead_behind(&range.a, &range.b)?;
+                let upstream = remote_branch
+                    .strip_prefix("refs/remotes/")
+                    .unwrap_or(&remote_branch);
+                // without a common commit the branch was probably pointed at the wrong upstream,
+                // so pushing or rebasing would both be wrong advice
+                let reason = if !range.has_common_history() {
+                    format!(
+                        "shares no history with {}; check that it tracks the right branch",
+                        upstream
+                    )
+                } else if range.is_descendant() {
+                    format!(
+                        "is {} ahead of {}; run `git push` to publish",
+                        commits(ahead),
+                        upstream
+                    )
+                } else if sync_context.is_current_branch() {
+                    format!(
+                        "has diverged from {} ({} ahead, {} behind); run `git up --rebase` to rebase it",
+                        upstream, ahead, behind
+                    )
+                } else {
+                    format!(
+                        "has diverged from {} ({} ahead, {} behind); check it out and run `git up --rebase` to rebase it",
+                        upstream, ahead, behind
+                    )
+                };
+                Ok(Step::Done(BranchOutcome::Warned {
+                    old: range.a,
+                    new: Some(range.b),
+                    reason,
+                }))
+            }
+        }
+        BranchStatus::RemoteBranchGone => {
+            let range = refs.range(&full_branch, full_default_branch)?;
+            let squashed = !range.is_ancestor()
+                && *detect_squash
+                && git::is_squash_merged(&full_branch, full_default_branch)
+                    .with_context(|| "failed to check for a squash merge")?;
+            let is_merged = range.is_ancestor() || squashed;
+            if is_merged && sync_context.is_protected() {
+                Ok(Step::Done(BranchOutcome::Skipped {
+                    old: Some(range.a),
+                    reason: SkipReason::Protected,
+                }))
+            } else if is_merged && *keep_current && sync_context.is_current_branch() {
+                Ok(Step::Done(BranchOutcome::Warned {
+                    old: range.a,
+                    new: None,
+                    reason: "current branch is merged and its upstream is gone; not deleting \
+                             while checked out"
+                        .to_string(),
+                }))
+            } else if is_merged {
+                Ok(plan_delete(sync_context, range.a, squashed))
+            } else if *rebase_gone
+                && !*delete_only
+                && !sync_context.is_current_branch()
+                && sync_context.linked_worktree_path().is_none()
+                && git::is_linear_since_merge_base(&full_branch, full_default_branch)?
+            {
+                Ok(Step::RebaseGone { old: range.a })
+            } else {
+                let remote = branches_to_remotes.get(local_branch).unwrap_or(remote);
+                let (ahead, behind) = git::ahead_behind(&range.a, &range.b)?;
+                Ok(Step::Done(BranchOutcome::Warned {
+                    old: range.a,
+                    new: None,
+                    reason: format!(
+                        "was deleted on {}, but appears not merged into {} ({} ahead, {} behind)",
+                        remote, default_branch, ahead, behind
+                    ),
+                }))
+            }
+        }
+        BranchStatus::RemoteRemoved(removed_remote) => Ok(Step::Done(BranchOutcome::Warned {
+            old: refs
+                .sha(&full_branch)
+                .cloned()
+                .with_context(|| format!("Can't resolve {}", full_branch))?,
+            new: None,
+            reason: format!("tracks remote {}, which no longer exists", removed_remote),
+        })),
+        BranchStatus::Unknown => Ok(Step::Done(BranchOutcome::Skipped {
+            old: None,
+            reason: SkipReason::NoRemoteBranch,
+        })),
+    }
+}
+
+// whether the branch is the current one is checked again here rather than trusted from
+// planning, since deleting the current branch checks out the default branch
+pub fn execute_step(sync_context: &SyncContext, step: Step) -> Result<BranchOutcome> {
+    let SyncContext {
+        local_branch,
+        autostash,
+        dry_run,
+        ..
+    } = sync_context;
+    let full_branch = format!("refs/heads/{}", local_branch);
+
+    match step {
+        Step::Done(outcome) => Ok(outcome),
+        Step::FastForward {
+            old,
+            new,
+            remote_branch,
+        } => {
+            let _guard = MUTATION_LOCK.lock();
+            if *dry_run {
+                debug!("Skipping update of {} in dry-run mode", local_branch);
+            } else if sync_context.is_current_branch() {
+                let stashed =
+                    *autostash && git::stash_push().with_context(|| "failed to stash changes")?;
+                let merge = git::fast_forward_merge(&remote_branch, None)
+                    .with_context(|| "failed to fast forward merge");
+                if stashed {
+                    git::stash_pop().with_context(|| "failed to restore stashed changes")?;
+                }
+                merge?;
+            } else if let Some(worktree_path) = sync_context.linked_worktree_path() {
+                // git refuses to move a branch checked out elsewhere, so merge from inside that
+                // worktree instead, which also updates its files
+                debug!(
+                    "Fast-forwarding {} in worktree {}",
+                    local_branch, worktree_path
+                );
+                git::fast_forward_merge(&remote_branch, Some(worktree_path)).with_context(
+                    || format!("failed to fast forward merge in {}", worktree_path),
+                )?;
+            } else {
+                git::update_ref(&full_branch, &remote_branch)
+                    .with_context(|| "failed to update ref")?;
+            }
+            Ok(BranchOutcome::Updated { old, new })
+        }
+        Step::Pull { old } => {
+            let _guard = MUTATION_LOCK.lock();
+            let new = if *dry_run {
+                debug!("Skipping pull of {} in dry-run mode", local_branch);
+                None
+            } else {
+                Some(git::pull(*autostash).with_context(|| "failed to pull")?)
+            };
+            Ok(BranchOutcome::Pulled { old, new })
+        }
+        Step::Rebase { old, remote_branch } => {
+            let _guard = MUTATION_LOCK.lock();
+            if *dry_run {
+                debug!("Skipping rebase of {} in dry-run mode", local_branch);
+            } else {
+                git::rebase(&remote_branch).with_context(|| "failed to rebase")?;
+            }
+            Ok(BranchOutcome::Rebased {
+                old,
+                new: None,
+                onto: remote_branch
+                    .strip_prefix("refs/remotes/")
+                    .unwrap_or(&remote_branch)
+                    .to_string(),
+            })
+        }
+        Step::Merge { old, remote_branch } => {
+            let _guard = MUTATION_LOCK.lock();
+            let new = if *dry_run {
+                debug!("Skipping merge into {} in dry-run mode", local_branch);
+                None
+            } else {
+                Some(git::merge(&remote_branch, *autostash).with_context(|| "failed to merge")?)
+            };
+            Ok(BranchOutcome::Merged {
+                old,
+                new,
+                from: remote_branch
+                    .strip_prefix("refs/remotes/")
+                    .unwrap_or(&remote_branch)
+                    .to_string(),
+            })
+        }
+        Step::Delete { old, squashed } => delete_merged_branch(sync_context, old, squashed),
+        Step::RebaseGone { old } => Ok(rebase_gone_branch(sync_context, &full_branch, old)),
+    }
+}
+
+// whether the branch has commits its upstream doesn't and the other way around, for
+// --on-conflict=abort
+pub fn has_diverged(sync_context: &SyncContext) -> Result<bool> {
+    match sync_context.determine_branch_status() {
+        BranchStatus::RemoteBranchExists(remote_branch) => {
+            let full_branch = format!("refs/heads/{}", sync_context.local_branch);
+            let range = sync_context.refs.range(&full_branch, &remote_branch)?;
+            Ok(!range.is_identical() && !range.is_ancestor() && !range.is_descendant())
+        }
+        _ => Ok(false),
+    }
+}
+
+// without upstreams, the only thing to do is delete branches merged into the default branch
+fn plan_local_branch(sync_context: &SyncContext, full_branch: &str) -> Result<Step> {
+    let range = sync_context
+        .refs
+        .range(full_branch, &sync_context.full_default_branch)?;
+    if range.is_ancestor() && !sync_context.is_protected() {
+        Ok(plan_delete(sync_context, range.a, false))
+    } else {
+        Ok(Step::Done(BranchOutcome::Skipped {
+            old: Some(range.a),
+            reason: SkipReason::NoRemoteBranch,
+        }))
+    }
+}
+
+fn plan_delete(sync_context: &SyncContext, old: String, squashed: bool) -> Step {
+    match sync_context.linked_worktree_path() {
+        Some(worktree_path) => Step::Done(skip_linked_worktree_branch(worktree_path)),
+        None => Step::Delete { old, squashed },
+    }
+}
+
+fn delete_merged_branch(
+    sync_context: &SyncContext,
+    old: String,
+    squashed: bool,
+) -> Result<BranchOutcome> {
+    let SyncContext {
+        default_branch,
+        full_default_branch,
+        local_branch,
+        autostash,
+        pre_delete,
+        archive,
+        backup_remote,
+        force,
+        confirm,
+        keep_deletes,
+        dry_run,
+        ..
+    } = sync_context;
+
+    let _guard = MUTATION_LOCK.lock();
+    if *keep_deletes {
+        debug!(
+            "Keeping {}; there's no terminal to confirm the delete at",
+            local_branch
+        );
+        return Ok(BranchOutcome::Skipped {
+            old: Some(old),
+            reason: SkipReason::NotConfirmed,
+        });
+    }
+    // the branch may have moved since it was planned, so its ancestry is checked again before
+    // the prompt, the hook or the backup, unless it was squashed or --force says not to
+    let force = *force || squashed;
+    let full_branch = format!("refs/heads/{}", local_branch);
+    if !force && !git::make_range(&full_branch, full_default_branch)?.is_ancestor() {
+        return Err(anyhow!(
+            "{} is not merged into {}; not deleting it",
+            local_branch,
+            full_default_branch
+        ));
+    }
+    if *confirm && !*dry_run && !prompt::confirm(&format!("Delete branch {}?", local_branch))? {
+        return Ok(BranchOutcome::Skipped {
+            old: Some(old),
+            reason: SkipReason::NotConfirmed,
+        });
+    }
+    if let Some(hook) = pre_delete {
+        if !*dry_run && !git::run_hook(hook, local_branch)? {
+            return Ok(BranchOutcome::Skipped {
+                old: Some(old),
+                reason: SkipReason::PreDeleteHook,
+            });
+        }
+    }
+    if let Some(backup_remote) = backup_remote {
+        if *dry_run {
+            debug!("Skipping backup of {} in dry-run mode", local_branch);
+        } else if let Err(e) = git::push(backup_remote, local_branch) {
+            debug!("{:#}", e);
+            return Ok(BranchOutcome::Skipped {
+                old: Some(old),
+                reason: SkipReason::BackupFailed(backup_remote.clone()),
+            });
+        }
+    }
+    if *dry_run {
+        debug!("Skipping deletion of {} in dry-run mode", local_branch);
+    } else {
+        if sync_context.is_current_branch() {
+            let stashed =
+                *autostash && git::stash_push().with_context(|| "failed to stash changes")?;
+            let checkout =
+                git::checkout(default_branch).with_context(|| "failed to checkout default branch");
+            if stashed {
+                git::stash_pop().with_context(|| "failed to restore stashed changes")?;
+            }
+            checkout?;
+        }
+        if *archive {
+            git::archive_branch(local_branch).with_context(|| "failed to archive local branch")?;
+        }
+        // git's own merge check compares against HEAD once the upstream is gone
+        if force || git::make_range(&full_branch, "HEAD")?.is_ancestor() {
+            git::delete_branch(local_branch, force)
+        } else {
+            git::delete_merged_branch(local_branch, full_default_branch)
+        }
+        .with_context(|| "failed to delete local branch")?;
+    }
+    Ok(BranchOutcome::Deleted { old })
+}
+
+fn rebase_gone_branch(sync_context: &SyncContext, full_branch: &str, old: String) -> BranchOutcome {
+    let SyncContext {
+        default_branch,
+        full_default_branch,
+        local_branch,
+        dry_run,
+        ..
+    } = sync_context;
+
+    let _guard = MUTATION_LOCK.lock();
+    let new = if *dry_run {
+        debug!("Skipping rebase of {} in dry-run mode", local_branch);
+        None
+    } else {
+        match git::rebase_detached(full_branch, full_default_branch) {
+            Ok(new) => Some(new),
+            Err(e) => {
+                debug!("Rebase of {} failed: {}", local_branch, e);
+                return BranchOutcome::Warned {
+                    old,
+                    new: None,
+                    reason: format!(
+                        "was deleted upstream and could not be rebased cleanly onto {}; left untouched",
+                        default_branch
+                    ),
+                };
+            }
+        }
+    };
+
+    BranchOutcome::Rebased {
+        old,
+        new,
+        onto: default_branch.clone(),
+    }
+}
+
+fn skip_linked_worktree_branch(worktree_path: &str) -> BranchOutcome {
+    BranchOutcome::Skipped {
+        old: None,
+        reason: SkipReason::LinkedWorktree(worktree_path.to_string()),
+    }
+}
+
+fn commits(count: usize) -> String {
+    if count == 1 {
+        "1 commit".to_string()
+    } else {
+        format!("{} commits", count)
+    }
+}
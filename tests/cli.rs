@@ -0,0 +1,847 @@
+mod common;
+
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{isolate, TestRepo};
+
+fn run_git_up(repo: &TestRepo, args: &[&str]) -> Output {
+    isolate(
+        Command::new(env!("CARGO_BIN_EXE_git-up"))
+            .args(args)
+            .arg("--color=never")
+            .current_dir(&repo.work),
+    )
+    .output()
+    .unwrap()
+}
+
+fn git_up(repo: &TestRepo, args: &[&str]) -> String {
+    let output = run_git_up(repo, args);
+    // 10 means branches were changed
+    assert!(
+        matches!(output.status.code(), Some(0 | 10)),
+        "git-up failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// a leftover lock file makes updating the branch fail
+fn lock_branch(repo: &TestRepo, branch: &str) {
+    let git_dir = repo.work.join(".git");
+    std::fs::write(git_dir.join(format!("refs/heads/{}.lock", branch)), "").unwrap();
+}
+
+#[test]
+fn prints_what_happened_to_each_branch() {
+    let repo = TestRepo::new();
+    let behind = repo.sha("behind");
+    let merged = repo.sha("merged");
+    let stdout = git_up(&repo, &[]);
+
+    assert_eq!(
+        stdout.lines().collect::<Vec<_>>(),
+        [
+            "Warning: ahead is 1 commit ahead of origin/ahead; run `git push` to publish"
+                .to_string(),
+            format!("Updated branch behind (was {}).", &behind[0..7]),
+            format!("Deleted branch merged (was {}).", &merged[0..7]),
+            "Warning: unmerged was deleted on origin, but appears not merged into main \
+             (1 ahead, 0 behind)"
+                .to_string(),
+        ]
+    );
+}
+
+#[test]
+fn explains_fast_forwards_in_the_reflog() {
+    let repo = TestRepo::new();
+    git_up(&repo, &["behind"]);
+
+    assert_eq!(
+        repo.git(&["reflog", "-1", "--format=%gs", "behind"]),
+        "git-up: fast-forward to origin/behind"
+    );
+}
+
+#[test]
+fn groups_branches_by_what_happened() {
+    let repo = TestRepo::new();
+    repo.git(&["branch", "another", "main"]);
+    repo.git(&["push", "--quiet", "-u", "origin", "another"]);
+    repo.git(&["push", "--quiet", "origin", "--delete", "another"]);
+    let stdout = git_up(&repo, &["--group"]);
+
+    let lines: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.split(" (").next().unwrap())
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            "Updated branch behind",
+            "Deleted branch another",
+            "Deleted branch merged",
+            "Warning: ahead is 1 commit ahead of origin/ahead; run `git push` to publish",
+            "Warning: unmerged was deleted on origin, but appears not merged into main",
+        ]
+    );
+}
+
+#[test]
+fn marks_dry_run_output() {
+    let repo = TestRepo::new();
+    let stdout = git_up(&repo, &["--dry-run", "behind"]);
+
+    assert_eq!(
+        stdout.trim(),
+        format!(
+            "Updated branch behind (was {}). (dry-run)",
+            &repo.sha("behind")[0..7]
+        )
+    );
+}
+
+#[test]
+fn abbreviates_shas_to_the_requested_length() {
+    let repo = TestRepo::new();
+    let stdout = git_up(&repo, &["--dry-run", "--abbrev", "12", "behind"]);
+
+    assert_eq!(
+        stdout.trim(),
+        format!(
+            "Updated branch behind (was {}). (dry-run)",
+            &repo.sha("behind")[0..12]
+        )
+    );
+}
+
+#[test]
+fn prints_json() {
+    let repo = TestRepo::new();
+    let stdout = git_up(&repo, &["--json", "main"]);
+    let main = repo.sha("main");
+
+    assert_eq!(
+        stdout.trim(),
+        format!(
+            "[{{\"branch\":\"main\",\"action\":\"skipped\",\"old_sha\":\"{0}\",\"new_sha\":\"{0}\",\"reason\":\"up to date\"}}]",
+            main
+        )
+    );
+}
+
+#[test]
+fn exits_non_zero_when_a_branch_fails() {
+    let repo = TestRepo::new();
+    lock_branch(&repo, "behind");
+    let output = run_git_up(&repo, &[]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout)
+        .contains("Error: behind failed to process branch: failed to update ref"));
+    // the remaining branches are still synced
+    assert!(!repo.has_branch("merged"));
+}
+
+#[test]
+fn exit_code_reports_whether_anything_changed() {
+    let repo = TestRepo::new();
+
+    assert_eq!(run_git_up(&repo, &["--dry-run"]).status.code(), Some(0));
+    assert_eq!(run_git_up(&repo, &[]).status.code(), Some(10));
+    assert_eq!(run_git_up(&repo, &[]).status.code(), Some(0));
+}
+
+#[test]
+fn fail_fast_stops_at_the_first_failure() {
+    let repo = TestRepo::new();
+    lock_branch(&repo, "behind");
+    let output = run_git_up(&repo, &["--fail-fast"]);
+
+    assert!(!output.status.success());
+    assert!(repo.has_branch("merged"));
+}
+
+#[test]
+fn sets_a_missing_remote_head() {
+    let repo = TestRepo::new();
+    let output = run_git_up(&repo, &["--set-head", "main"]);
+
+    // progress goes to stderr so stdout only carries results
+    assert!(String::from_utf8_lossy(&output.stdout).is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr).trim(),
+        "Set origin/HEAD to main."
+    );
+    assert_eq!(
+        repo.git(&["symbolic-ref", "refs/remotes/origin/HEAD"]),
+        "refs/remotes/origin/main"
+    );
+    assert_eq!(git_up(&repo, &["--set-head", "main"]), "");
+}
+
+#[test]
+fn refuses_to_run_during_a_merge() {
+    let repo = TestRepo::new();
+    let head = repo.sha("HEAD");
+    std::fs::write(repo.work.join(".git/MERGE_HEAD"), head).unwrap();
+    let output = run_git_up(&repo, &[]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("A merge is in progress; finish or abort it before running git-up"));
+    assert!(repo.has_branch("merged"));
+}
+
+#[test]
+fn quiet_only_prints_warnings() {
+    let repo = TestRepo::new();
+    let stdout = git_up(&repo, &["--quiet"]);
+
+    assert!(stdout.lines().all(|line| line.starts_with("Warning:")));
+    assert_eq!(stdout.lines().count(), 2);
+}
+
+#[test]
+fn prints_completions_outside_a_repository() {
+    for shell in ["bash", "zsh", "fish"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_git-up"))
+            .args(["completions", shell])
+            .current_dir(std::env::temp_dir())
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("dry-run"));
+        for subcommand in ["sync", "status", "clean", "undo"] {
+            assert!(
+                stdout.contains(subcommand),
+                "{} missing from {}",
+                subcommand,
+                shell
+            );
+        }
+    }
+}
+
+#[test]
+fn refuses_to_run_outside_a_working_tree() {
+    let repo = TestRepo::new();
+    let output = isolate(
+        Command::new(env!("CARGO_BIN_EXE_git-up")).current_dir(repo.work.join("../origin.git")),
+    )
+    .output()
+    .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("git-up must be run inside a git working tree"));
+}
+
+#[test]
+fn runs_against_another_directory() {
+    let repo = TestRepo::new();
+    let output = isolate(
+        Command::new(env!("CARGO_BIN_EXE_git-up"))
+            .args(["--color=never", "-C"])
+            .arg(&repo.work)
+            .current_dir(std::env::temp_dir()),
+    )
+    .output()
+    .unwrap();
+
+    assert_eq!(output.status.code(), Some(10));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Deleted branch merged"));
+    assert!(!repo.has_branch("merged"));
+}
+
+#[test]
+fn syncs_a_repeated_branch_once() {
+    let repo = TestRepo::new();
+    let stdout = git_up(&repo, &["--dry-run", "behind", "behind"]);
+
+    assert_eq!(stdout.lines().count(), 1);
+}
+
+#[test]
+fn refuses_a_default_branch_missing_on_the_remote() {
+    let repo = TestRepo::new();
+    let output = run_git_up(&repo, &["--default-branch", "develop"]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Default branch develop does not exist on origin"));
+}
+
+#[test]
+fn plan_changes_nothing_unless_confirmed() {
+    let repo = TestRepo::new();
+    let behind = repo.sha("behind");
+    let output = run_git_up(&repo, &["--plan"]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr)
+            .lines()
+            .collect::<Vec<_>>(),
+        [
+            "Will update branch behind.",
+            "Will delete branch merged.",
+            "1 to update, 1 to delete, 2 warnings.",
+            "Proceed? [y/N] ",
+        ]
+    );
+    assert!(output.stdout.is_empty());
+    assert_eq!(repo.sha("behind"), behind);
+    assert!(repo.has_branch("merged"));
+}
+
+#[test]
+fn plan_says_when_a_cap_is_exceeded() {
+    let repo = TestRepo::new();
+    let output = run_git_up(&repo, &["--plan", "--max-deletes", "0"]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Would delete 1 branch, more than --max-deletes 0. Proceed? [y/N] "));
+    assert!(repo.has_branch("merged"));
+}
+
+#[test]
+fn plan_runs_once_confirmed() {
+    let repo = TestRepo::new();
+    let mut child = isolate(
+        Command::new(env!("CARGO_BIN_EXE_git-up"))
+            .args(["--plan", "--color=never"])
+            .current_dir(&repo.work)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped()),
+    )
+    .spawn()
+    .unwrap();
+    child.stdin.take().unwrap().write_all(b"y\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(10));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Updated branch behind"));
+    assert_eq!(repo.sha("behind"), repo.sha("origin/behind"));
+    assert!(!repo.has_branch("merged"));
+}
+
+#[test]
+fn runs_the_pre_delete_hook() {
+    let repo = TestRepo::new();
+    let merged = repo.sha("merged");
+    repo.git(&["config", "git-up.preDelete", "git tag archive/{branch}"]);
+    git_up(&repo, &[]);
+
+    assert!(!repo.has_branch("merged"));
+    assert_eq!(repo.sha("archive/merged"), merged);
+}
+
+#[test]
+fn runs_a_pre_delete_hook_that_quotes_the_branch() {
+    let repo = TestRepo::new();
+    let merged = repo.sha("merged");
+    repo.git(&["config", "git-up.preDelete", "git tag 'archive/{branch}'"]);
+    git_up(&repo, &[]);
+
+    assert!(!repo.has_branch("merged"));
+    assert_eq!(repo.sha("archive/merged"), merged);
+}
+
+#[test]
+fn keeps_a_branch_when_the_pre_delete_hook_fails() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "git-up.preDelete", "false"]);
+    let stdout = git_up(&repo, &[]);
+
+    assert!(stdout.contains("Kept branch merged (the pre-delete hook failed)."));
+    assert!(repo.has_branch("merged"));
+}
+
+#[test]
+fn keeps_branches_when_deletes_cant_be_confirmed() {
+    let repo = TestRepo::new();
+    let stdout = git_up(&repo, &["--confirm-deletes"]);
+
+    assert!(stdout.contains("Updated branch behind"));
+    assert!(stdout.contains("Kept branch merged."));
+    assert!(repo.has_branch("merged"));
+}
+
+#[test]
+fn archives_deleted_branches() {
+    let repo = TestRepo::new();
+    let merged = repo.sha("merged");
+    git_up(&repo, &["--archive"]);
+
+    assert!(!repo.has_branch("merged"));
+    assert_eq!(repo.sha("refs/git-up/archive/merged"), merged);
+}
+
+#[test]
+fn pushes_branches_to_the_backup_remote_before_deleting_them() {
+    let repo = TestRepo::new();
+    let merged = repo.sha("merged");
+    let backup = repo.work.join(".git/backup.git");
+    repo.git(&["init", "--quiet", "--bare", backup.to_str().unwrap()]);
+    repo.git(&["remote", "add", "backup", backup.to_str().unwrap()]);
+    git_up(&repo, &["--backup-remote", "backup"]);
+
+    assert!(!repo.has_branch("merged"));
+    assert_eq!(
+        repo.git(&["--git-dir", backup.to_str().unwrap(), "rev-parse", "merged"]),
+        merged
+    );
+
+    // a branch that can't be backed up is kept
+    let repo = TestRepo::new();
+    repo.git(&["remote", "add", "backup", "/nonexistent/backup.git"]);
+    let stdout = git_up(&repo, &["--backup-remote", "backup"]);
+
+    assert!(stdout.contains("Kept branch merged (pushing it to backup failed)."));
+    assert!(repo.has_branch("merged"));
+}
+
+#[test]
+fn fast_forwards_branches_checked_out_in_other_worktrees() {
+    let repo = TestRepo::new();
+    let worktree = repo.work.join("../worktree");
+    let worktree = worktree.to_str().unwrap();
+    repo.git(&["worktree", "add", "--quiet", worktree, "behind"]);
+    let stdout = git_up(&repo, &[]);
+
+    assert!(stdout.contains("Updated branch behind"));
+    assert_eq!(repo.sha("behind"), repo.sha("origin/behind"));
+    // the worktree's files moved along with the branch
+    assert_eq!(repo.git(&["-C", worktree, "status", "--porcelain"]), "");
+}
+
+#[test]
+fn shows_what_a_fast_forward_brought_in() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "--quiet", "origin/behind"]);
+    std::fs::write(repo.work.join("README"), "hello\n").unwrap();
+    repo.git(&["add", "README"]);
+    repo.commit("add a readme");
+    repo.git(&["push", "--quiet", "origin", "HEAD:behind"]);
+    repo.git(&["checkout", "--quiet", "main"]);
+    let stdout = git_up(&repo, &["--show-stat", "behind"]);
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(lines[0].starts_with("Updated branch behind"));
+    assert!(lines.last().unwrap().contains("1 file changed"));
+}
+
+#[test]
+fn local_only_deletes_merged_branches_without_a_remote() {
+    let repo = TestRepo::new();
+    repo.git(&["remote", "remove", "origin"]);
+    let stdout = git_up(&repo, &["--local-only"]);
+
+    assert!(stdout.contains("Deleted branch merged"));
+    assert!(!repo.has_branch("merged"));
+    assert!(repo.has_branch("main"));
+    assert!(repo.has_branch("ahead"));
+    assert!(repo.has_branch("unmerged"));
+}
+
+#[test]
+fn logs_ndjson_to_stderr() {
+    let repo = TestRepo::new();
+    let output = run_git_up(&repo, &["--log-format", "ndjson"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stderr
+        .lines()
+        .all(|line| line.starts_with("{\"ts\":") && line.ends_with('}')));
+    assert!(
+        stderr.contains("\"level\":\"info\",\"target\":\"git_up\",\"message\":\"Fetching origin\"")
+    );
+    assert!(stderr.contains("\"message\":\"Branch merged: deleted\""));
+    // results are still printed for people on stdout
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Deleted branch merged"));
+}
+
+#[test]
+fn resumes_an_interrupted_run() {
+    let repo = TestRepo::new();
+    let state = repo.work.join(".git/git-up-state.json");
+    lock_branch(&repo, "behind");
+    // progress is only recorded when asked for
+    let output = run_git_up(&repo, &["--fail-fast"]);
+    assert!(!output.status.success());
+    assert!(!state.exists());
+
+    let output = run_git_up(&repo, &["--fail-fast", "--resume"]);
+    assert!(!output.status.success());
+    assert!(std::fs::read_to_string(&state)
+        .unwrap()
+        .contains("{\"branch\":\"ahead\""));
+
+    std::fs::remove_file(repo.work.join(".git/refs/heads/behind.lock")).unwrap();
+    repo.git(&["config", "git-up.resume", "true"]);
+    let stdout = git_up(&repo, &[]);
+
+    assert!(!stdout.contains("Warning: ahead"));
+    assert!(stdout.contains("Updated branch behind"));
+    assert!(!state.exists());
+}
+
+#[test]
+fn passes_extra_fetch_args() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "git-up.fetchArgs", "--no-progress --no-tags"]);
+    git_up(&repo, &["--fetch-arg", "--jobs=2"]);
+
+    let output = run_git_up(&repo, &["--fetch-arg", "origin"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("Fetch argument origin is a remote; git-up adds the remote to fetch itself"));
+}
+
+#[test]
+fn honors_conditionally_included_config() {
+    let repo = TestRepo::new();
+    let included = repo.work.join("../protected.gitconfig");
+    std::fs::write(&included, "[git-up]\n\tprotected = merged\n").unwrap();
+    let git_dir = repo.work.join(".git").canonicalize().unwrap();
+    repo.git(&[
+        "config",
+        &format!("includeIf.gitdir:{}.path", git_dir.display()),
+        included.to_str().unwrap(),
+    ]);
+    let stdout = git_up(&repo, &[]);
+
+    assert!(stdout.contains("Kept protected branch merged"));
+    assert!(repo.has_branch("merged"));
+}
+
+#[test]
+fn only_current_syncs_just_the_checked_out_branch() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "--quiet", "behind"]);
+    let stdout = git_up(&repo, &["--only-current"]);
+
+    assert_eq!(stdout.lines().count(), 1);
+    assert!(stdout.starts_with("Updated branch behind"));
+    assert!(repo.has_branch("merged"));
+
+    repo.git(&["checkout", "--quiet", "--detach"]);
+    let output = run_git_up(&repo, &["--only-current"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr).trim(),
+        "Not on a branch; nothing to sync."
+    );
+}
+
+#[test]
+fn does_nothing_before_the_first_commit() {
+    let repo = TestRepo::new();
+    let fresh = repo.work.join("fresh");
+    std::fs::create_dir(&fresh).unwrap();
+    isolate(
+        Command::new("git")
+            .args(["init", "--quiet", "-b", "main"])
+            .current_dir(&fresh),
+    )
+    .status()
+    .unwrap();
+    isolate(
+        Command::new("git")
+            .args(["remote", "add", "origin", "../../origin.git"])
+            .current_dir(&fresh),
+    )
+    .status()
+    .unwrap();
+    let output = run_git_up(&repo, &["-C", fresh.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr).trim(),
+        "No commits yet; nothing to sync."
+    );
+}
+
+#[test]
+fn undoes_recorded_deletes() {
+    let repo = TestRepo::new();
+    let merged = repo.sha("merged");
+    let record = repo.work.join(".git/git-up-undo");
+    git_up(&repo, &["--write-undo", record.to_str().unwrap()]);
+
+    assert!(!repo.has_branch("merged"));
+    let output = isolate(
+        Command::new(env!("CARGO_BIN_EXE_git-up"))
+            .args(["undo", record.to_str().unwrap()])
+            .current_dir(&repo.work),
+    )
+    .output()
+    .unwrap();
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Restored branch merged"));
+    assert_eq!(repo.sha("merged"), merged);
+}
+
+#[test]
+fn refuses_to_delete_more_than_the_cap() {
+    let repo = TestRepo::new();
+    let output = run_git_up(&repo, &["--max-deletes", "0"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "Would delete 1 branch, more than --max-deletes 0; pass --allow-over-cap to go ahead"
+    ));
+    assert!(repo.has_branch("merged"));
+    assert_ne!(repo.sha("behind"), repo.sha("origin/behind"));
+
+    // --force only skips the merge check, not the cap
+    let output = run_git_up(&repo, &["--max-deletes", "0", "--force"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(repo.has_branch("merged"));
+
+    git_up(&repo, &["--max-deletes", "0", "--allow-over-cap"]);
+    assert!(!repo.has_branch("merged"));
+}
+
+#[test]
+fn runs_capped_plans_concurrently() {
+    let repo = TestRepo::new();
+    git_up(&repo, &["--max-deletes", "1", "--jobs", "4"]);
+
+    assert!(!repo.has_branch("merged"));
+    assert_eq!(repo.sha("behind"), repo.sha("origin/behind"));
+}
+
+#[test]
+fn warnings_as_errors_fails_on_warnings() {
+    let repo = TestRepo::new();
+    let output = run_git_up(&repo, &["--dry-run", "--warnings-as-errors"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    // the warnings are still printed, so the log says what to fix
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Warning: ahead is 1 commit ahead"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("2 branches have warnings"));
+    assert!(repo.has_branch("merged"));
+
+    git_up(&repo, &["--dry-run", "--warnings-as-errors", "behind"]);
+}
+
+#[test]
+fn digests_new_commits_on_the_default_branch() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "--quiet", "--detach", "main"]);
+    for n in 1..=12 {
+        repo.commit(&format!("upstream {}", n));
+    }
+    repo.git(&["push", "--quiet", "origin", "HEAD:main"]);
+    repo.git(&["checkout", "--quiet", "main"]);
+    let output = run_git_up(&repo, &["--dry-run", "--digest"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stderr.contains("New on origin/main:"));
+    assert!(stderr.contains(" upstream 12\n"));
+    assert!(stderr.contains(" upstream 3\n"));
+    assert!(!stderr.contains(" upstream 2\n"));
+    assert!(stderr.contains("... and 2 more"));
+}
+
+#[test]
+fn prints_timings() {
+    let repo = TestRepo::new();
+    let output = run_git_up(&repo, &["--dry-run", "--timings"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stderr.contains("Timings: fetch "), "{}", stderr);
+    assert!(stderr.contains("s, total "));
+}
+
+#[test]
+fn fail_on_protected_fails_when_a_protected_branch_is_spared() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "git-up.protected", "merge*"]);
+    let output = run_git_up(&repo, &["--dry-run", "--fail-on-protected"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Kept protected branch merged"));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("1 protected branch is merged and its upstream is gone"));
+
+    git_up(&repo, &["--dry-run", "--fail-on-protected", "behind"]);
+}
+
+#[test]
+fn status_changes_nothing() {
+    let repo = TestRepo::new();
+    let behind = repo.sha("behind");
+    let stdout = git_up(&repo, &["status"]);
+
+    assert!(stdout.contains("Deleted branch merged"));
+    assert!(stdout.contains("(dry-run)"));
+    assert!(repo.has_branch("merged"));
+    assert_eq!(repo.sha("behind"), behind);
+}
+
+#[test]
+fn clean_only_deletes_branches() {
+    let repo = TestRepo::new();
+    let behind = repo.sha("behind");
+    let stdout = git_up(&repo, &["clean"]);
+
+    assert!(stdout.contains("Deleted branch merged"));
+    assert!(!stdout.contains("branch behind"));
+    assert!(!stdout.contains("Warning: ahead"));
+    assert!(!repo.has_branch("merged"));
+    assert_eq!(repo.sha("behind"), behind);
+}
+
+#[test]
+fn prints_porcelain() {
+    let repo = TestRepo::new();
+    let behind = repo.sha("behind");
+    let merged = repo.sha("merged");
+    let stdout = git_up(&repo, &["--dry-run", "--porcelain", "behind", "merged"]);
+
+    assert_eq!(
+        stdout,
+        format!(
+            "updated behind {} {}\ndeleted merged {} -\n",
+            behind,
+            repo.sha("origin/behind"),
+            merged
+        )
+    );
+}
+
+#[test]
+fn formats_each_branch_with_a_template() {
+    let repo = TestRepo::new();
+    let behind = repo.sha("behind");
+    let stdout = git_up(
+        &repo,
+        &[
+            "--dry-run",
+            "--format",
+            "{branch}: {old} -> {new} ({ahead} ahead, {behind} behind)",
+            "behind",
+        ],
+    );
+
+    assert_eq!(
+        stdout.trim(),
+        format!(
+            "behind: {} -> {} (0 ahead, 1 behind)",
+            &behind[0..7],
+            &repo.sha("origin/behind")[0..7]
+        )
+    );
+
+    let output = run_git_up(&repo, &["--format", "{sha}"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown placeholder {sha}"));
+}
+
+#[test]
+fn retries_an_unreachable_remote() {
+    let repo = TestRepo::new();
+    repo.git(&["remote", "set-url", "origin", "/nonexistent/origin.git"]);
+    let output = run_git_up(&repo, &["--fetch-retries", "1"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr.contains("Remote origin is unreachable"),
+        "{}",
+        stderr
+    );
+    assert_eq!(stderr.matches("retrying in").count(), 1);
+}
+
+#[test]
+fn syncs_several_repositories() {
+    let first = TestRepo::new();
+    let second = TestRepo::new();
+    let first_path = first.work.to_str().unwrap();
+    let second_path = second.work.to_str().unwrap();
+    let stdout = git_up(&first, &["-C", first_path, "-C", second_path]);
+
+    assert!(stdout.contains(&format!("==> {}", first_path)));
+    assert!(stdout.contains(&format!("==> {}", second_path)));
+    assert!(!first.has_branch("merged"));
+    assert!(!second.has_branch("merged"));
+}
+
+#[test]
+fn refuses_machine_output_for_several_repositories() {
+    let first = TestRepo::new();
+    let second = TestRepo::new();
+    let first_path = first.work.to_str().unwrap();
+    let second_path = second.work.to_str().unwrap();
+    for format in ["--json", "--porcelain"] {
+        let output = run_git_up(&first, &[format, "-C", first_path, "-C", second_path]);
+
+        assert_eq!(output.status.code(), Some(1));
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("--json and --porcelain describe a single repository"));
+    }
+    assert!(first.has_branch("merged"));
+    assert!(second.has_branch("merged"));
+}
+
+#[test]
+fn keeps_going_when_a_listed_repository_fails() {
+    let repo = TestRepo::new();
+    let list = repo.work.join(".git/repos");
+    std::fs::write(&list, "# services\n../../missing\n\n../../work\n").unwrap();
+    let output = run_git_up(&repo, &["--repos-file", list.to_str().unwrap()]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("missing"), "{}", stderr);
+    assert!(stderr.contains("Synced 1 of 2 repositories; 1 failed."));
+    assert!(!repo.has_branch("merged"));
+}
+
+#[test]
+fn exits_with_130_when_interrupted() {
+    let repo = TestRepo::new();
+    // the fetch hangs until it is stopped
+    repo.git(&[
+        "remote",
+        "set-url",
+        "origin",
+        "ssh://example.invalid/repo.git",
+    ]);
+    let mut child = isolate(
+        Command::new(env!("CARGO_BIN_EXE_git-up"))
+            .current_dir(&repo.work)
+            .env("GIT_SSH_COMMAND", "sleep 5")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null()),
+    )
+    .spawn()
+    .unwrap();
+    thread::sleep(Duration::from_millis(500));
+
+    let interrupted = Instant::now();
+    Command::new("kill")
+        .args(["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    let status = child.wait().unwrap();
+
+    assert_eq!(status.code(), Some(130));
+    assert!(interrupted.elapsed() < Duration::from_secs(4));
+}
@@ -0,0 +1,137 @@
+// shared by several test binaries, each of which uses a different subset
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// a bare `origin` repository and a clone of it at `work`, both removed on drop. the
+// clone starts out with these branches:
+//
+//     main      up to date with origin/main
+//     behind    behind origin/behind, so it can be fast-forwarded
+//     ahead     has a commit that was never pushed
+//     merged    merged into main, then deleted on origin
+//     unmerged  deleted on origin without being merged
+pub struct TestRepo {
+    root: PathBuf,
+    pub work: PathBuf,
+}
+
+impl TestRepo {
+    pub fn new() -> Self {
+        let root = env::temp_dir().join(format!(
+            "git-up-test-{}-{}",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        let repo = Self {
+            work: root.join("work"),
+            root,
+        };
+        git(
+            &repo.root,
+            &["init", "--quiet", "--bare", "-b", "main", "origin.git"],
+        );
+        git(&repo.root, &["clone", "--quiet", "origin.git", "work"]);
+
+        repo.commit("initial");
+        repo.git(&["push", "--quiet", "-u", "origin", "main"]);
+
+        repo.git(&["checkout", "--quiet", "-b", "behind"]);
+        repo.commit("behind");
+        repo.git(&["push", "--quiet", "-u", "origin", "behind"]);
+        repo.git(&["checkout", "--quiet", "main"]);
+        repo.git(&["branch", "-f", "behind", "main"]);
+
+        repo.git(&["checkout", "--quiet", "-b", "ahead"]);
+        repo.git(&["push", "--quiet", "-u", "origin", "ahead"]);
+        repo.commit("ahead");
+
+        repo.git(&["checkout", "--quiet", "-b", "merged", "main"]);
+        repo.commit("merged");
+        repo.git(&["push", "--quiet", "-u", "origin", "merged"]);
+        repo.git(&["checkout", "--quiet", "main"]);
+        repo.git(&["merge", "--quiet", "--ff-only", "merged"]);
+        repo.git(&["push", "--quiet", "origin", "main"]);
+        repo.git(&["push", "--quiet", "origin", "--delete", "merged"]);
+
+        repo.git(&["checkout", "--quiet", "-b", "unmerged"]);
+        repo.commit("unmerged");
+        repo.git(&["push", "--quiet", "-u", "origin", "unmerged"]);
+        repo.git(&["push", "--quiet", "origin", "--delete", "unmerged"]);
+
+        repo.git(&["checkout", "--quiet", "main"]);
+        repo
+    }
+
+    pub fn git(&self, args: &[&str]) -> String {
+        git(&self.work, args)
+    }
+
+    pub fn commit(&self, message: &str) -> String {
+        self.git(&["commit", "--quiet", "--allow-empty", "-m", message]);
+        self.sha("HEAD")
+    }
+
+    // commits with both dates set to `date`, e.g. "2001-01-01T00:00:00"
+    pub fn commit_dated(&self, message: &str, date: &str) -> String {
+        let output = isolate(
+            Command::new("git")
+                .args(["commit", "--quiet", "--allow-empty", "-m", message])
+                .env("GIT_AUTHOR_DATE", date)
+                .env("GIT_COMMITTER_DATE", date)
+                .current_dir(&self.work),
+        )
+        .output()
+        .unwrap();
+        assert!(output.status.success());
+        self.sha("HEAD")
+    }
+
+    pub fn sha(&self, rev: &str) -> String {
+        self.git(&["rev-parse", rev])
+    }
+
+    pub fn has_branch(&self, branch: &str) -> bool {
+        !self.git(&["branch", "--list", branch]).trim().is_empty()
+    }
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+// keeps the user's own git config out of the tests
+pub fn isolate(command: &mut Command) -> &mut Command {
+    command
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_AUTHOR_NAME", "git-up")
+        .env("GIT_AUTHOR_EMAIL", "git-up@example.com")
+        .env("GIT_COMMITTER_NAME", "git-up")
+        .env("GIT_COMMITTER_EMAIL", "git-up@example.com")
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = isolate(Command::new("git").args(args).current_dir(dir))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
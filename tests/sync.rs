@@ -0,0 +1,698 @@
+mod common;
+
+use std::{
+    collections::HashMap, env, ffi::OsStr, os::unix::ffi::OsStrExt, process::Command, sync::Mutex,
+    time::Duration,
+};
+
+use common::TestRepo;
+use git_up::{
+    execute_step, git, process_branch,
+    report::{BranchOutcome, SkipReason},
+    Config, Event, OnConflict, Step, Summary, SyncContext,
+};
+
+// the library runs git in the current directory, which is shared by every test thread
+static CWD_LOCK: Mutex<()> = Mutex::new(());
+
+fn in_repo<T>(repo: &TestRepo, f: impl FnOnce() -> T) -> T {
+    let _guard = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    env::set_var("GIT_CONFIG_GLOBAL", "/dev/null");
+    env::set_var("GIT_CONFIG_NOSYSTEM", "1");
+    env::set_var("GIT_AUTHOR_NAME", "git-up");
+    env::set_var("GIT_AUTHOR_EMAIL", "git-up@example.com");
+    env::set_var("GIT_COMMITTER_NAME", "git-up");
+    env::set_var("GIT_COMMITTER_EMAIL", "git-up@example.com");
+    env::set_current_dir(&repo.work).unwrap();
+    f()
+}
+
+fn outcome<'a>(summary: &'a Summary, branch: &str) -> &'a BranchOutcome {
+    &summary
+        .branches
+        .iter()
+        .find(|report| report.branch == branch)
+        .unwrap_or_else(|| panic!("no report for {}", branch))
+        .outcome
+}
+
+fn context(branch: &str) -> SyncContext {
+    let branches_to_remotes = ["behind", "ahead", "merged", "unmerged", "main"]
+        .iter()
+        .map(|branch| (branch.to_string(), "origin".to_string()))
+        .collect();
+    SyncContext {
+        remote: "origin".to_string(),
+        default_branch: "main".to_string(),
+        full_default_branch: "refs/remotes/origin/main".to_string(),
+        local_branch: branch.to_string(),
+        current_branch: Some("main".to_string()),
+        branches_to_remotes,
+        synced_remotes: vec!["origin".to_string()],
+        remotes: vec!["origin".to_string()],
+        protected_branches: vec!["main".to_string()],
+        worktree_branches: HashMap::new(),
+        refs: git::get_refs().unwrap(),
+        rebase: false,
+        pull: false,
+        autostash: false,
+        detect_squash: false,
+        rebase_gone: false,
+        prune_merged: false,
+        local_only: false,
+        archive: false,
+        backup_remote: None,
+        force: false,
+        keep_current: false,
+        delete_only: false,
+        on_conflict: OnConflict::Warn,
+        pre_delete: None,
+        since: None,
+        confirm: false,
+        keep_deletes: false,
+        dry_run: false,
+    }
+}
+
+#[test]
+fn syncs_every_branch() {
+    let repo = TestRepo::new();
+    let remote_behind = repo.sha("origin/behind");
+    let summary = in_repo(&repo, || git_up::run(Config::default())).unwrap();
+
+    assert!(matches!(
+        outcome(&summary, "main"),
+        BranchOutcome::UpToDate { .. }
+    ));
+    assert!(
+        matches!(outcome(&summary, "behind"), BranchOutcome::Updated { new, .. } if *new == remote_behind)
+    );
+    assert!(
+        matches!(outcome(&summary, "ahead"), BranchOutcome::Warned { reason, .. }
+        if reason == "is 1 commit ahead of origin/ahead; run `git push` to publish")
+    );
+    assert!(matches!(
+        outcome(&summary, "merged"),
+        BranchOutcome::Deleted { .. }
+    ));
+    assert!(
+        matches!(outcome(&summary, "unmerged"), BranchOutcome::Warned { reason, .. }
+        if reason.starts_with("was deleted on origin, but appears not merged into main"))
+    );
+
+    assert_eq!(repo.sha("behind"), remote_behind);
+    assert!(!repo.has_branch("merged"));
+    assert!(repo.has_branch("unmerged"));
+}
+
+#[test]
+fn dry_run_leaves_branches_alone() {
+    let repo = TestRepo::new();
+    let behind = repo.sha("behind");
+    let config = Config {
+        dry_run: true,
+        ..Config::default()
+    };
+    let summary = in_repo(&repo, || git_up::run(config)).unwrap();
+
+    assert!(matches!(
+        outcome(&summary, "behind"),
+        BranchOutcome::Updated { .. }
+    ));
+    assert!(matches!(
+        outcome(&summary, "merged"),
+        BranchOutcome::Deleted { .. }
+    ));
+    assert_eq!(repo.sha("behind"), behind);
+    assert!(repo.has_branch("merged"));
+}
+
+#[test]
+fn keep_current_leaves_a_merged_current_branch_checked_out() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "--quiet", "merged"]);
+    let config = Config {
+        keep_current: true,
+        ..Config::default()
+    };
+    let summary = in_repo(&repo, || git_up::run(config)).unwrap();
+
+    assert!(
+        matches!(outcome(&summary, "merged"), BranchOutcome::Warned { reason, .. }
+        if reason.starts_with("current branch is merged and its upstream is gone"))
+    );
+    assert!(repo.has_branch("merged"));
+    assert_eq!(repo.git(&["branch", "--show-current"]).trim(), "merged");
+}
+
+#[test]
+fn remote_filter_syncs_only_branches_tracking_that_remote() {
+    let repo = TestRepo::new();
+    repo.git(&["remote", "add", "fork", "../origin.git"]);
+    repo.git(&["config", "branch.behind.remote", "fork"]);
+    repo.git(&["branch", "untracked", "main"]);
+    let branches = |remote_filter: &str| {
+        let config = Config {
+            dry_run: true,
+            all_remotes: true,
+            remote_filter: Some(remote_filter.to_string()),
+            ..Config::default()
+        };
+        let summary = in_repo(&repo, || git_up::run(config)).unwrap();
+        summary
+            .branches
+            .into_iter()
+            .map(|report| report.branch)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(branches("fork"), ["behind"]);
+    assert_eq!(
+        branches("origin"),
+        ["ahead", "main", "merged", "unmerged", "untracked"]
+    );
+}
+
+#[test]
+fn skips_fast_forwarding_a_dirty_current_branch() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "--quiet", "behind"]);
+    std::fs::write(repo.work.join("notes"), "wip\n").unwrap();
+    repo.git(&["add", "notes"]);
+    let behind = repo.sha("behind");
+    let summary = in_repo(&repo, || git_up::run(Config::default())).unwrap();
+
+    assert!(
+        matches!(outcome(&summary, "behind"), BranchOutcome::Warned { reason, .. }
+        if reason.starts_with("is checked out with uncommitted changes; skipping fast-forward"))
+    );
+    assert_eq!(repo.sha("behind"), behind);
+
+    let config = Config {
+        autostash: true,
+        ..Config::default()
+    };
+    in_repo(&repo, || git_up::run(config)).unwrap();
+    assert_eq!(repo.sha("behind"), repo.sha("origin/behind"));
+    assert_eq!(repo.git(&["status", "--porcelain"]).trim(), "A  notes");
+}
+
+#[test]
+fn keeps_protected_branches() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "git-up.protected", "merge*"]);
+    let summary = in_repo(&repo, || git_up::run(Config::default())).unwrap();
+
+    assert!(matches!(
+        outcome(&summary, "merged"),
+        BranchOutcome::Skipped {
+            reason: SkipReason::Protected,
+            ..
+        }
+    ));
+    assert!(repo.has_branch("merged"));
+}
+
+#[test]
+fn process_branch_decides_from_refs() {
+    let repo = TestRepo::new();
+    repo.git(&["fetch", "--quiet", "--prune"]);
+    let ahead = repo.sha("ahead");
+
+    in_repo(&repo, || {
+        assert!(matches!(
+            process_branch(&context("main")).unwrap(),
+            BranchOutcome::UpToDate { .. }
+        ));
+        assert!(matches!(
+            process_branch(&context("ahead")).unwrap(),
+            BranchOutcome::Warned { old, .. } if old == ahead
+        ));
+        assert!(matches!(
+            process_branch(&context("unmerged")).unwrap(),
+            BranchOutcome::Warned { .. }
+        ));
+        assert!(matches!(
+            process_branch(&SyncContext {
+                protected_branches: vec!["merged".to_string()],
+                ..context("merged")
+            })
+            .unwrap(),
+            BranchOutcome::Skipped {
+                reason: SkipReason::Protected,
+                ..
+            }
+        ));
+    });
+}
+
+#[test]
+fn treats_an_upstream_pruned_after_listing_refs_as_gone() {
+    let repo = TestRepo::new();
+    repo.git(&["fetch", "--quiet", "--prune"]);
+    repo.git(&["branch", "--quiet", "-f", "behind", "origin/behind"]);
+    repo.git(&["branch", "--quiet", "scratch"]);
+
+    in_repo(&repo, || {
+        let sync_context = context("behind");
+        repo.git(&["update-ref", "-d", "refs/remotes/origin/behind"]);
+        assert!(matches!(
+            process_branch(&sync_context).unwrap(),
+            BranchOutcome::Warned { reason, .. } if reason.starts_with("was deleted on origin")
+        ));
+        // never pushed, so there is nothing to compare against
+        assert!(matches!(
+            process_branch(&context("scratch")).unwrap(),
+            BranchOutcome::Skipped {
+                reason: SkipReason::NoRemoteBranch,
+                ..
+            }
+        ));
+    });
+}
+
+#[test]
+fn warns_about_branches_tracking_a_removed_remote() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "branch.behind.remote", "fork"]);
+    let summary = in_repo(&repo, || git_up::run(Config::default())).unwrap();
+
+    assert!(
+        matches!(outcome(&summary, "behind"), BranchOutcome::Warned { reason, .. }
+        if reason == "tracks remote fork, which no longer exists")
+    );
+}
+
+#[test]
+fn fast_forwards_a_branch_tracking_another_local_branch() {
+    let repo = TestRepo::new();
+    repo.git(&["branch", "--quiet", "follower", "main~1"]);
+    repo.git(&["branch", "--quiet", "--set-upstream-to", "main", "follower"]);
+    repo.git(&["branch", "--quiet", "lost", "main"]);
+    repo.git(&["config", "branch.lost.remote", "."]);
+    repo.git(&["config", "branch.lost.merge", "refs/heads/missing"]);
+    let main = repo.sha("main");
+    let summary = in_repo(&repo, || git_up::run(Config::default())).unwrap();
+
+    assert!(
+        matches!(outcome(&summary, "follower"), BranchOutcome::Updated { new, .. } if *new == main)
+    );
+    assert!(matches!(
+        outcome(&summary, "lost"),
+        BranchOutcome::Skipped {
+            reason: SkipReason::NoRemoteBranch,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn reports_a_ref_that_does_not_resolve() {
+    let repo = TestRepo::new();
+
+    in_repo(&repo, || {
+        let error = git::make_range("refs/heads/main", "refs/remotes/origin/missing")
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Can't resolve refs/remotes/origin/missing; the ref doesn't exist"
+        );
+
+        // a gone branch is compared against the default branch, which has to resolve
+        let sync_context = SyncContext {
+            full_default_branch: "refs/remotes/origin/missing".to_string(),
+            ..context("merged")
+        };
+        let error = process_branch(&sync_context).err().unwrap();
+        assert!(format!("{:#}", error).contains("Can't resolve refs/remotes/origin/missing"));
+        assert!(repo.has_branch("merged"));
+    });
+}
+
+#[test]
+fn guesses_the_default_branch_without_origin_head() {
+    let repo = TestRepo::new();
+    repo.git(&["update-ref", "refs/remotes/origin/master", "main"]);
+    repo.git(&["update-ref", "-d", "refs/remotes/origin/main"]);
+
+    in_repo(&repo, || {
+        assert_eq!(git::get_default_branch("origin", false).unwrap(), "master");
+    });
+}
+
+#[test]
+fn asks_the_remote_for_its_head_before_guessing() {
+    let repo = TestRepo::new();
+    repo.git(&["update-ref", "refs/remotes/origin/master", "main"]);
+
+    in_repo(&repo, || {
+        assert_eq!(git::get_default_branch("origin", true).unwrap(), "main");
+    });
+}
+
+#[test]
+fn refuses_to_delete_a_branch_that_is_no_longer_merged() {
+    let repo = TestRepo::new();
+    repo.git(&["fetch", "--quiet", "--prune"]);
+    let unmerged = repo.sha("unmerged");
+
+    in_repo(&repo, || {
+        // a delete planned before the branch moved is checked again when it runs
+        let step = || Step::Delete {
+            old: unmerged.clone(),
+            squashed: false,
+        };
+        // before the hook gets a chance to run
+        let sync_context = SyncContext {
+            pre_delete: Some("git tag hooked".to_string()),
+            ..context("unmerged")
+        };
+        assert!(execute_step(&sync_context, step()).is_err());
+        assert!(repo.has_branch("unmerged"));
+        assert!(repo.git(&["tag", "--list", "hooked"]).trim().is_empty());
+
+        let sync_context = SyncContext {
+            force: true,
+            ..context("unmerged")
+        };
+        assert!(matches!(
+            execute_step(&sync_context, step()).unwrap(),
+            BranchOutcome::Deleted { .. }
+        ));
+        assert!(!repo.has_branch("unmerged"));
+    });
+}
+
+#[test]
+fn deletes_a_branch_merged_on_the_server_before_the_default_branch_catches_up() {
+    // git's merge check would otherwise compare against a stale main or an unrelated branch
+    for (archive, checked_out) in [(false, "main"), (true, "main"), (false, "elsewhere")] {
+        let repo = TestRepo::new();
+        repo.git(&["branch", "--quiet", "elsewhere", "main"]);
+        repo.git(&["checkout", "--quiet", "-b", "feature", "main"]);
+        let feature = repo.commit("feature");
+        repo.git(&["push", "--quiet", "-u", "origin", "feature"]);
+        // merged into main on the server, which also deletes the branch
+        repo.git(&["push", "--quiet", "origin", "feature:main"]);
+        repo.git(&["push", "--quiet", "origin", "--delete", "feature"]);
+        repo.git(&["checkout", "--quiet", checked_out]);
+        let config = Config {
+            archive,
+            ..Config::default()
+        };
+        let summary = in_repo(&repo, || git_up::run(config)).unwrap();
+
+        assert!(matches!(
+            outcome(&summary, "feature"),
+            BranchOutcome::Deleted { .. }
+        ));
+        assert!(matches!(
+            outcome(&summary, "main"),
+            BranchOutcome::Updated { .. }
+        ));
+        assert!(!repo.has_branch("feature"));
+        if archive {
+            assert_eq!(repo.sha("refs/git-up/archive/feature"), feature);
+        }
+    }
+}
+
+#[test]
+fn fast_forwards_the_current_default_branch_with_or_without_tracking_config() {
+    for tracking in [true, false] {
+        let repo = TestRepo::new();
+        repo.git(&["checkout", "--quiet", "-b", "upstream-work", "main"]);
+        let remote_main = repo.commit("upstream work");
+        repo.git(&["push", "--quiet", "origin", "upstream-work:main"]);
+        repo.git(&["checkout", "--quiet", "main"]);
+        repo.git(&["branch", "--quiet", "-D", "upstream-work"]);
+        if !tracking {
+            repo.git(&["branch", "--quiet", "--unset-upstream", "main"]);
+        }
+        let summary = in_repo(&repo, || git_up::run(Config::default())).unwrap();
+
+        assert!(
+            matches!(outcome(&summary, "main"), BranchOutcome::Updated { new, .. }
+            if *new == remote_main)
+        );
+        assert_eq!(repo.sha("HEAD"), remote_main);
+    }
+}
+
+#[test]
+fn prune_merged_deletes_merged_branches_with_an_upstream() {
+    let repo = TestRepo::new();
+    let config = Config {
+        prune_merged: true,
+        ..Config::default()
+    };
+    let summary = in_repo(&repo, || git_up::run(config)).unwrap();
+
+    // behind points at a commit that is already on main
+    assert!(matches!(
+        outcome(&summary, "behind"),
+        BranchOutcome::Deleted { .. }
+    ));
+    assert!(matches!(
+        outcome(&summary, "main"),
+        BranchOutcome::UpToDate { .. }
+    ));
+    assert!(matches!(
+        outcome(&summary, "ahead"),
+        BranchOutcome::Warned { .. }
+    ));
+    assert!(!repo.has_branch("behind"));
+    assert!(repo.has_branch("main"));
+}
+
+#[test]
+fn handles_branch_names_with_dots() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "--quiet", "-b", "release.1.0"]);
+    repo.git(&["push", "--quiet", "-u", "origin", "release.1.0"]);
+    repo.git(&["checkout", "--quiet", "main"]);
+    repo.git(&["push", "--quiet", "origin", "--delete", "release.1.0"]);
+    let summary = in_repo(&repo, || git_up::run(Config::default())).unwrap();
+
+    assert!(matches!(
+        outcome(&summary, "release.1.0"),
+        BranchOutcome::Deleted { .. }
+    ));
+    assert!(!repo.has_branch("release.1.0"));
+}
+
+#[test]
+fn warns_that_a_diverged_branch_needs_a_rebase() {
+    let repo = TestRepo::new();
+    repo.git(&["push", "--quiet", "--force", "origin", "main:ahead"]);
+    repo.git(&["fetch", "--quiet", "--prune"]);
+
+    in_repo(&repo, || {
+        assert!(matches!(
+            process_branch(&context("ahead")).unwrap(),
+            BranchOutcome::Warned { reason, .. } if reason == "has diverged from origin/ahead \
+            (1 ahead, 1 behind); check it out and run `git up --rebase` to rebase it"
+        ));
+    });
+}
+
+#[test]
+fn pull_merges_a_diverged_current_branch() {
+    let repo = TestRepo::new();
+    diverge_main(&repo);
+    repo.git(&["config", "pull.rebase", "false"]);
+    let config = Config {
+        pull: true,
+        branches: vec!["main".to_string()],
+        ..Config::default()
+    };
+    let summary = in_repo(&repo, || git_up::run(config)).unwrap();
+
+    let head = repo.sha("HEAD");
+    assert!(
+        matches!(outcome(&summary, "main"), BranchOutcome::Pulled { new: Some(new), .. }
+        if *new == head)
+    );
+    repo.git(&["merge-base", "--is-ancestor", "origin/main", "main"]);
+}
+
+// main gets a commit of its own while origin/main moves on
+fn diverge_main(repo: &TestRepo) {
+    repo.git(&["checkout", "--quiet", "-b", "upstream-work", "main"]);
+    repo.commit("upstream work");
+    repo.git(&["push", "--quiet", "origin", "upstream-work:main"]);
+    repo.git(&["checkout", "--quiet", "main"]);
+    repo.git(&["branch", "--quiet", "-D", "upstream-work"]);
+    repo.commit("local work");
+}
+
+#[test]
+fn warns_about_a_branch_only_ahead_of_its_upstream() {
+    let repo = TestRepo::new();
+    let summary = in_repo(&repo, || git_up::run(Config::default())).unwrap();
+
+    assert!(
+        matches!(outcome(&summary, "ahead"), BranchOutcome::Warned { reason, .. }
+        if reason == "is 1 commit ahead of origin/ahead; run `git push` to publish")
+    );
+}
+
+#[test]
+fn warns_about_a_branch_diverged_from_its_upstream() {
+    let repo = TestRepo::new();
+    diverge_main(&repo);
+    let summary = in_repo(&repo, || git_up::run(Config::default())).unwrap();
+
+    assert!(
+        matches!(outcome(&summary, "main"), BranchOutcome::Warned { reason, .. }
+        if reason.starts_with("has diverged from origin/main (1 ahead, 1 behind)"))
+    );
+}
+
+#[test]
+fn warns_about_a_branch_unrelated_to_its_upstream() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "--quiet", "--orphan", "lonely"]);
+    repo.commit("lonely");
+    repo.git(&["checkout", "--quiet", "main"]);
+    repo.git(&[
+        "branch",
+        "--quiet",
+        "--set-upstream-to",
+        "origin/main",
+        "lonely",
+    ]);
+    let summary = in_repo(&repo, || git_up::run(Config::default())).unwrap();
+
+    assert!(
+        matches!(outcome(&summary, "lonely"), BranchOutcome::Warned { reason, .. }
+        if reason == "shares no history with origin/main; check that it tracks the right branch")
+    );
+}
+
+#[test]
+fn on_conflict_merge_merges_the_upstream_into_the_current_branch() {
+    let repo = TestRepo::new();
+    diverge_main(&repo);
+    let config = Config {
+        on_conflict: OnConflict::Merge,
+        branches: vec!["main".to_string()],
+        ..Config::default()
+    };
+    let summary = in_repo(&repo, || git_up::run(config)).unwrap();
+
+    let head = repo.sha("HEAD");
+    assert!(
+        matches!(outcome(&summary, "main"), BranchOutcome::Merged { new: Some(new), from, .. }
+        if *new == head && from == "origin/main")
+    );
+    repo.git(&["merge-base", "--is-ancestor", "origin/main", "main"]);
+}
+
+#[test]
+fn on_conflict_abort_stops_before_changing_any_branch() {
+    let repo = TestRepo::new();
+    diverge_main(&repo);
+    let behind = repo.sha("behind");
+    let config = Config {
+        on_conflict: OnConflict::Abort,
+        ..Config::default()
+    };
+    let error = in_repo(&repo, || git_up::run(config)).err().unwrap();
+
+    assert!(error
+        .to_string()
+        .starts_with("main has diverged from its upstream"));
+    assert_eq!(repo.sha("behind"), behind);
+    assert!(repo.has_branch("merged"));
+}
+
+#[test]
+fn reports_non_utf8_branch_names_instead_of_panicking() {
+    let repo = TestRepo::new();
+    // git accepts any bytes in a ref name apart from a few punctuation and control characters
+    let status = common::isolate(
+        Command::new("git")
+            .arg("update-ref")
+            .arg(OsStr::from_bytes(b"refs/heads/caf\xe9"))
+            .arg("main")
+            .current_dir(&repo.work),
+    )
+    .status()
+    .unwrap();
+    assert!(status.success());
+    let error = in_repo(&repo, || git_up::run(Config::default()))
+        .err()
+        .unwrap();
+
+    assert!(
+        format!("{:#}", error).contains("not valid UTF-8"),
+        "{:#}",
+        error
+    );
+}
+
+#[test]
+fn since_skips_inactive_branches() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "--quiet", "-b", "stale", "main"]);
+    repo.commit_dated("stale", "2001-01-01T00:00:00");
+    repo.git(&["push", "--quiet", "-u", "origin", "stale"]);
+    repo.git(&["checkout", "--quiet", "main"]);
+    repo.git(&["push", "--quiet", "origin", "--delete", "stale"]);
+    let config = Config {
+        since: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+        ..Config::default()
+    };
+    let summary = in_repo(&repo, || git_up::run(config)).unwrap();
+
+    assert!(matches!(
+        outcome(&summary, "stale"),
+        BranchOutcome::Skipped {
+            reason: SkipReason::Inactive,
+            ..
+        }
+    ));
+    assert!(matches!(
+        outcome(&summary, "merged"),
+        BranchOutcome::Deleted { .. }
+    ));
+    assert!(repo.has_branch("stale"));
+}
+
+#[test]
+fn stale_lists_old_branches_with_an_upstream() {
+    let repo = TestRepo::new();
+    repo.git(&["checkout", "--quiet", "-b", "stale", "main"]);
+    repo.commit_dated("stale", "2001-01-01T00:00:00");
+    repo.git(&["push", "--quiet", "-u", "origin", "stale"]);
+    // gone branches are left to the cleanup instead of being reported
+    repo.git(&["checkout", "--quiet", "-b", "abandoned", "main"]);
+    repo.commit_dated("abandoned", "2001-01-01T00:00:00");
+    repo.git(&["push", "--quiet", "-u", "origin", "abandoned"]);
+    repo.git(&["push", "--quiet", "origin", "--delete", "abandoned"]);
+    repo.git(&["checkout", "--quiet", "main"]);
+    let config = Config {
+        stale: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+        ..Config::default()
+    };
+    let mut stale = Vec::new();
+    in_repo(&repo, || {
+        git_up::run_with(config, |event| {
+            if let Event::Stale(branches) = event {
+                stale.extend(
+                    branches
+                        .iter()
+                        .map(|branch| (branch.branch.clone(), branch.last_commit.clone())),
+                );
+            }
+        })
+    })
+    .unwrap();
+
+    assert_eq!(stale.len(), 1);
+    assert_eq!(stale[0].0, "stale");
+    assert!(stale[0].1.ends_with("years ago"));
+    assert!(repo.has_branch("stale"));
+}